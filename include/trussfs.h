@@ -5,6 +5,104 @@ typedef struct trussfs_ctx trussfs_ctx;
 typedef uint64_t listhandle_t;
 typedef uint64_t archivehandle_t;
 typedef uint64_t watcherhandle_t;
+typedef uint64_t mounthandle_t;
+typedef uint64_t readerhandle_t;
+typedef uint64_t archivewriterhandle_t;
+typedef uint64_t dirlisthandle_t;
+typedef uint64_t jobhandle_t;
+typedef uint64_t stringhandle_t;
+typedef uint64_t eventlisthandle_t;
+typedef uint64_t reloadhandle_t;
+typedef uint64_t entrylisthandle_t;
+
+enum {
+    TRUSSFS_KIND_OTHER = 0,
+    TRUSSFS_KIND_FILE = 1,
+    TRUSSFS_KIND_DIR = 2
+};
+
+// times are seconds since the Unix epoch (0 if unavailable); btime is the
+// birth (creation) time, which some filesystems and kernels don't record
+typedef struct trussfs_dirent {
+    const char* name;
+    uint32_t kind;
+    bool is_symlink;
+    uint64_t size;
+    double mtime;
+    double btime;
+    uint32_t permissions;
+} trussfs_dirent;
+
+enum {
+    TRUSSFS_COPY_SKIP = 0,
+    TRUSSFS_COPY_OVERWRITE = 1,
+    TRUSSFS_COPY_OVERWRITE_IF_NEWER = 2
+};
+
+enum {
+    TRUSSFS_JOB_FAILED = -1,
+    TRUSSFS_JOB_RUNNING = 0,
+    TRUSSFS_JOB_FINISHED = 1
+};
+
+typedef struct trussfs_job_progress {
+    int32_t status;
+    uint64_t files_total;
+    uint64_t files_copied;
+    uint64_t files_skipped;
+    uint64_t bytes_total;
+    uint64_t bytes_copied;
+    // files_copied + files_skipped and bytes_copied + bytes_skipped reach
+    // the totals once a job has finished successfully
+    uint64_t bytes_skipped;
+} trussfs_job_progress;
+
+enum {
+    TRUSSFS_EVENT_OTHER = 0,
+    TRUSSFS_EVENT_CREATE = 1,
+    TRUSSFS_EVENT_REMOVE = 2,
+    TRUSSFS_EVENT_MODIFY = 3,
+    TRUSSFS_EVENT_RENAME = 4,
+    TRUSSFS_EVENT_ACCESS = 5
+};
+
+enum {
+    TRUSSFS_SUBKIND_ANY = 0,
+    TRUSSFS_SUBKIND_FILE = 1,
+    TRUSSFS_SUBKIND_FOLDER = 2,
+    TRUSSFS_SUBKIND_DATA = 3,
+    TRUSSFS_SUBKIND_METADATA = 4,
+    TRUSSFS_SUBKIND_OPEN = 5,
+    TRUSSFS_SUBKIND_CLOSE = 6,
+    TRUSSFS_SUBKIND_READ = 7,
+    TRUSSFS_SUBKIND_RENAME_FROM = 8,
+    TRUSSFS_SUBKIND_RENAME_TO = 9
+};
+
+enum {
+    TRUSSFS_BACKEND_NATIVE = 0,
+    TRUSSFS_BACKEND_POLL = 1,
+    TRUSSFS_BACKEND_AUTO = 2 // native, falling back to polling if that fails
+};
+
+enum {
+    TRUSSFS_WATCHER_KIND_OTHER = 0,
+    TRUSSFS_WATCHER_KIND_INOTIFY = 1,
+    TRUSSFS_WATCHER_KIND_FSEVENT = 2,
+    TRUSSFS_WATCHER_KIND_KQUEUE = 3,
+    TRUSSFS_WATCHER_KIND_POLL = 4,
+    TRUSSFS_WATCHER_KIND_WINDOWS = 5
+};
+
+// A paired rename has subkind ANY with path = old name and path2 = new name;
+// path2 is NULL otherwise. timestamp is monotonic seconds.
+typedef struct trussfs_watch_event {
+    uint32_t kind;
+    uint32_t subkind;
+    const char* path;
+    const char* path2;
+    double timestamp;
+} trussfs_watch_event;
 
 uint64_t trussfs_version();
 trussfs_ctx* trussfs_init();
@@ -15,27 +113,186 @@ void trussfs_clear_error(trussfs_ctx* ctx);
 
 uint64_t trussfs_recursive_makedir(trussfs_ctx* ctx, const char* path);
 
+uint64_t trussfs_file_size(trussfs_ctx* ctx, const char* path);
+int64_t trussfs_read_file(trussfs_ctx* ctx, const char* path, uint8_t* dest, uint64_t dest_size);
+bool trussfs_write_file(trussfs_ctx* ctx, const char* path, const uint8_t* data, uint64_t len);
+bool trussfs_write_file_atomic(trussfs_ctx* ctx, const char* path, const uint8_t* data, uint64_t len);
+bool trussfs_append_file(trussfs_ctx* ctx, const char* path, const uint8_t* data, uint64_t len);
+
+// these return the list of affected paths; with dry_run nothing is changed
+listhandle_t trussfs_copy_file(trussfs_ctx* ctx, const char* src, const char* dst, bool dry_run);
+listhandle_t trussfs_rename(trussfs_ctx* ctx, const char* src, const char* dst, bool dry_run);
+listhandle_t trussfs_remove_file(trussfs_ctx* ctx, const char* path, bool dry_run);
+listhandle_t trussfs_remove_dir(trussfs_ctx* ctx, const char* path, bool dry_run);
+listhandle_t trussfs_remove_tree(trussfs_ctx* ctx, const char* path, bool dry_run);
+
+// copies run on a background thread; poll trussfs_job_progress until the
+// status is no longer TRUSSFS_JOB_RUNNING. Freeing a running job cancels it.
+// Each file is written to a temp file and renamed into place, so a
+// cancelled or failed copy leaves no partial file behind.
+jobhandle_t trussfs_copy_tree(trussfs_ctx* ctx, const char* src, const char* dst, uint32_t policy);
+jobhandle_t trussfs_copy_tree_archive(trussfs_ctx* ctx, archivehandle_t archive, const char* prefix, const char* dst, uint32_t policy);
+int32_t trussfs_job_progress(trussfs_ctx* ctx, jobhandle_t job, trussfs_job_progress* progress);
+void trussfs_job_free(trussfs_ctx* ctx, jobhandle_t job);
+
 const char* trussfs_working_dir(trussfs_ctx* ctx);
 const char* trussfs_binary_dir(trussfs_ctx* ctx);
 
 bool trussfs_is_handle_valid(uint64_t handle);
 
 watcherhandle_t trussfs_watcher_create(trussfs_ctx* ctx, const char* path, bool recursive);
+// coalesces each path's events into one, reported after debounce_ms of quiet
+watcherhandle_t trussfs_watcher_create_debounced(trussfs_ctx* ctx, const char* path, bool recursive, uint32_t debounce_ms);
+// poll_interval_ms = 0 uses the default interval (1s)
+watcherhandle_t trussfs_watcher_create_ex(trussfs_ctx* ctx, const char* path, bool recursive, uint32_t backend, uint32_t poll_interval_ms, uint32_t debounce_ms);
+uint32_t trussfs_watcher_kind(trussfs_ctx* ctx, watcherhandle_t watcher);
 bool trussfs_watcher_augment(trussfs_ctx* ctx, watcherhandle_t watcher, const char* path, bool recursive);
+// path must match the string given to create/augment
+bool trussfs_watcher_remove_path(trussfs_ctx* ctx, watcherhandle_t watcher, const char* path);
+listhandle_t trussfs_watcher_list_paths(trussfs_ctx* ctx, watcherhandle_t watcher);
+// block until the watcher has events or errors to poll; timeout_ms < 0
+// waits forever
+bool trussfs_watcher_wait(trussfs_ctx* ctx, watcherhandle_t watcher, int32_t timeout_ms);
+// returns the index of a watcher with events or errors ready, -1 on
+// timeout, -2 on error
+int64_t trussfs_watcher_wait_any(trussfs_ctx* ctx, const watcherhandle_t* watchers, uint64_t count, int32_t timeout_ms);
+// glob lists (either may be an invalid handle) are matched against full
+// event paths, e.g. "**/.git/**" or "*.swp"
+bool trussfs_watcher_set_filter(trussfs_ctx* ctx, watcherhandle_t watcher, listhandle_t include_globs, listhandle_t exclude_globs);
+// Push mode: once a callback is set, events are delivered to it (as typed
+// events) from a dedicated dispatch thread and no longer show up in polls
+// or waits; errors and health are still polled as usual.
+// - the callback runs on the dispatch thread, one event at a time, never
+//   concurrently with itself for the same watcher
+// - the event and its paths are only valid for the duration of the call
+// - the callback must not call into trussfs with the same ctx
+// - setting NULL (or a new callback) first stops the dispatch thread,
+//   blocking until the batch of events in progress has been delivered;
+//   after that the old callback is never invoked again and its userdata
+//   can be released. Events not yet taken are left for polling.
+//   trussfs_watcher_free does the same.
+typedef void (*trussfs_watch_callback)(void* userdata, const trussfs_watch_event* event);
+bool trussfs_watcher_set_callback(trussfs_ctx* ctx, watcherhandle_t watcher, trussfs_watch_callback callback, void* userdata);
 void trussfs_watcher_free(trussfs_ctx* ctx, watcherhandle_t watcher);
 listhandle_t trussfs_watcher_poll(trussfs_ctx* ctx, watcherhandle_t watcher);
+// backend errors and removed watch roots; INVALID_HANDLE if there are none
+listhandle_t trussfs_watcher_poll_errors(trussfs_ctx* ctx, watcherhandle_t watcher);
+// false once the watcher has hit an error or lost a watched path
+bool trussfs_watcher_is_healthy(trussfs_ctx* ctx, watcherhandle_t watcher);
+eventlisthandle_t trussfs_watcher_poll_ex(trussfs_ctx* ctx, watcherhandle_t watcher);
+void trussfs_eventlist_free(trussfs_ctx* ctx, eventlisthandle_t list);
+uint64_t trussfs_eventlist_length(trussfs_ctx* ctx, eventlisthandle_t list);
+const trussfs_watch_event* trussfs_eventlist_get(trussfs_ctx* ctx, eventlisthandle_t list, uint64_t index);
+
+// Hot-reload tracking: register each asset under the name it was loaded by,
+// then poll for the (sorted) names whose source files changed since the
+// last poll. Names loaded from an archive go stale when the zip changes.
+// If a watched directory is deleted, poll sets last_error; the directory is
+// watched again once it reappears, and the names in it then go stale.
+reloadhandle_t trussfs_reload_create(trussfs_ctx* ctx, uint32_t debounce_ms);
+void trussfs_reload_free(trussfs_ctx* ctx, reloadhandle_t tracker);
+bool trussfs_reload_track_file(trussfs_ctx* ctx, reloadhandle_t tracker, const char* name, const char* path);
+// the name is the virtual path; its source is whatever currently serves it
+bool trussfs_reload_track_vfs(trussfs_ctx* ctx, reloadhandle_t tracker, const char* vpath);
+bool trussfs_reload_track_archive(trussfs_ctx* ctx, reloadhandle_t tracker, const char* name, archivehandle_t archive);
+bool trussfs_reload_untrack(trussfs_ctx* ctx, reloadhandle_t tracker, const char* name);
+listhandle_t trussfs_reload_poll(trussfs_ctx* ctx, reloadhandle_t tracker);
 
 archivehandle_t trussfs_archive_mount(trussfs_ctx* ctx, const char* path);
+// opens a zip stored as an entry of parent; the new archive stays valid
+// after parent is freed (data from a mount_memory parent without copy is
+// copied) and doesn't follow parent's auto-reload. An uncompressed entry is
+// read in place rather than loaded into memory.
+archivehandle_t trussfs_archive_mount_nested(trussfs_ctx* ctx, archivehandle_t parent, const char* entry_name);
+// the file must not be truncated or rewritten in place while mapped
+// (replace it instead, e.g. by renaming a new file over it)
+archivehandle_t trussfs_archive_mount_mapped(trussfs_ctx* ctx, const char* path);
+// without copy, data must stay alive and unchanged until the archive and
+// everything opened from it (readers, copy jobs) have been freed
+archivehandle_t trussfs_archive_mount_memory(trussfs_ctx* ctx, const uint8_t* data, uint64_t len, bool copy);
+// password for encrypted (ZipCrypto or AES) entries, NULL to clear; reading
+// an encrypted entry fails with "Password required for <name>" when no
+// password is set and "Wrong password for <name>" when it doesn't match
+// (ZipCrypto can't always tell, so a wrong password may instead show up as
+// a checksum or decompression error); sizes and listings never need it
+bool trussfs_archive_set_password(trussfs_ctx* ctx, archivehandle_t archive, const char* password);
+// with auto-reload on, an archive whose zip is rebuilt on disk (mtime or
+// size changed) is transparently reopened before its next use; the
+// generation counts those reopens. If the new zip can't be opened yet, the
+// error is logged and the previous contents keep being served, which works
+// when the zip is replaced (e.g. renamed over), not rewritten in place.
+bool trussfs_archive_set_auto_reload(trussfs_ctx* ctx, archivehandle_t archive, bool enabled);
+uint64_t trussfs_archive_generation(trussfs_ctx* ctx, archivehandle_t archive);
 void trussfs_archive_free(trussfs_ctx* ctx, archivehandle_t archive);
 listhandle_t trussfs_archive_list(trussfs_ctx* ctx, archivehandle_t archive);
+// names directly inside dir ("a/b", or "" / NULL for the root), sorted;
+// directories implied by entry paths are included
+listhandle_t trussfs_archive_list_dir(trussfs_ctx* ctx, archivehandle_t archive, const char* dir, bool files_only);
+bool trussfs_archive_exists(trussfs_ctx* ctx, archivehandle_t archive, const char* path);
+// typed listing; unsafe names (absolute or with "..") are listed with
+// is_unsafe set rather than dropped. mtime is seconds since the Unix epoch,
+// reading the zip's timezone-less times as local time (as copy_tree does);
+// compression is the raw zip method (0 = stored, 8 = deflate), and
+// unix_mode is 0 when the archive doesn't record one.
+entrylisthandle_t trussfs_archive_list_ex(trussfs_ctx* ctx, archivehandle_t archive);
+void trussfs_entrylist_free(trussfs_ctx* ctx, entrylisthandle_t list);
+uint64_t trussfs_entrylist_length(trussfs_ctx* ctx, entrylisthandle_t list);
+const char* trussfs_entrylist_name(trussfs_ctx* ctx, entrylisthandle_t list, uint64_t index);
+const char* trussfs_entrylist_comment(trussfs_ctx* ctx, entrylisthandle_t list, uint64_t index);
+uint64_t trussfs_entrylist_index(trussfs_ctx* ctx, entrylisthandle_t list, uint64_t index);
+bool trussfs_entrylist_is_dir(trussfs_ctx* ctx, entrylisthandle_t list, uint64_t index);
+bool trussfs_entrylist_is_unsafe(trussfs_ctx* ctx, entrylisthandle_t list, uint64_t index);
+bool trussfs_entrylist_is_encrypted(trussfs_ctx* ctx, entrylisthandle_t list, uint64_t index);
+uint64_t trussfs_entrylist_size(trussfs_ctx* ctx, entrylisthandle_t list, uint64_t index);
+uint64_t trussfs_entrylist_compressed_size(trussfs_ctx* ctx, entrylisthandle_t list, uint64_t index);
+uint32_t trussfs_entrylist_compression(trussfs_ctx* ctx, entrylisthandle_t list, uint64_t index);
+uint32_t trussfs_entrylist_crc32(trussfs_ctx* ctx, entrylisthandle_t list, uint64_t index);
+double trussfs_entrylist_mtime(trussfs_ctx* ctx, entrylisthandle_t list, uint64_t index);
+uint32_t trussfs_entrylist_unix_mode(trussfs_ctx* ctx, entrylisthandle_t list, uint64_t index);
 uint64_t trussfs_archive_filesize_name(trussfs_ctx* ctx, archivehandle_t archive, const char* name);
 uint64_t trussfs_archive_filesize_index(trussfs_ctx* ctx, archivehandle_t archive, uint64_t index);
 int64_t trussfs_archive_read_name(trussfs_ctx* ctx, archivehandle_t archive, const char* name, uint8_t* dest, uint64_t dest_size);
 int64_t trussfs_archive_read_index(trussfs_ctx* ctx, archivehandle_t archive, uint64_t index, uint8_t* dest, uint64_t dest_size);
+readerhandle_t trussfs_archive_open_entry(trussfs_ctx* ctx, archivehandle_t archive, const char* name);
+int64_t trussfs_reader_read(trussfs_ctx* ctx, readerhandle_t reader, uint8_t* dest, uint64_t n);
+void trussfs_reader_close(trussfs_ctx* ctx, readerhandle_t reader);
+
+// compression_level: negative = default deflate, 0 = stored, 1-9 = deflate level;
+// any other level fails without adding anything, leaving the writer usable
+archivewriterhandle_t trussfs_archive_create(trussfs_ctx* ctx, const char* path);
+bool trussfs_archive_add_file(trussfs_ctx* ctx, archivewriterhandle_t writer, const char* name, const uint8_t* data, uint64_t len, int32_t compression_level);
+bool trussfs_archive_add_dir(trussfs_ctx* ctx, archivewriterhandle_t writer, const char* name);
+bool trussfs_archive_finish(trussfs_ctx* ctx, archivewriterhandle_t writer);
 
 listhandle_t trussfs_list_dir(trussfs_ctx* ctx, const char* path, bool files_only, bool include_metadata);
+dirlisthandle_t trussfs_list_dir_ex(trussfs_ctx* ctx, const char* path, bool files_only);
+void trussfs_dirlist_free(trussfs_ctx* ctx, dirlisthandle_t list);
+uint64_t trussfs_dirlist_length(trussfs_ctx* ctx, dirlisthandle_t list);
+const trussfs_dirent* trussfs_dirlist_get(trussfs_ctx* ctx, dirlisthandle_t list, uint64_t index);
+
+// globs may be NULL or empty; max_depth 0 = unlimited
+listhandle_t trussfs_walk_dir(trussfs_ctx* ctx, const char* path, const char* include_glob, const char* exclude_glob, uint32_t max_depth, bool follow_symlinks);
+
+mounthandle_t trussfs_vfs_mount(trussfs_ctx* ctx, const char* prefix, const char* path, int64_t priority);
+mounthandle_t trussfs_vfs_mount_archive(trussfs_ctx* ctx, const char* prefix, archivehandle_t archive, int64_t priority);
+void trussfs_vfs_unmount(trussfs_ctx* ctx, mounthandle_t mount);
+uint64_t trussfs_vfs_filesize(trussfs_ctx* ctx, const char* path);
+int64_t trussfs_vfs_read(trussfs_ctx* ctx, const char* path, uint8_t* dest, uint64_t dest_size);
+listhandle_t trussfs_vfs_list(trussfs_ctx* ctx, const char* path, bool files_only);
+bool trussfs_vfs_exists(trussfs_ctx* ctx, const char* path);
 
 listhandle_t trussfs_split_path(trussfs_ctx* ctx, const char* path);
+stringhandle_t trussfs_path_join(trussfs_ctx* ctx, const char* base, const char* part);
+stringhandle_t trussfs_path_normalize(trussfs_ctx* ctx, const char* path);
+stringhandle_t trussfs_path_canonicalize(trussfs_ctx* ctx, const char* path);
+stringhandle_t trussfs_path_relative(trussfs_ctx* ctx, const char* base, const char* target);
+// the parent of a bare name like "a" is "."; a root has no parent
+stringhandle_t trussfs_path_parent(trussfs_ctx* ctx, const char* path);
+stringhandle_t trussfs_path_extension(trussfs_ctx* ctx, const char* path);
+stringhandle_t trussfs_path_stem(trussfs_ctx* ctx, const char* path);
+
+const char* trussfs_string_get(trussfs_ctx* ctx, stringhandle_t str);
+void trussfs_string_free(trussfs_ctx* ctx, stringhandle_t str);
 
 listhandle_t trussfs_list_new(trussfs_ctx* ctx);
 void trussfs_list_free(trussfs_ctx* ctx, listhandle_t list);
@@ -1,12 +1,320 @@
 use crate::context::StringList;
+use crate::vfs::normalize_vpath;
+use log::{info, warn};
+use memmap2::Mmap;
+use std::collections::{BTreeMap, HashMap};
 use std::ffi::CString;
-use std::fs::File;
-use std::io::{BufReader, Read};
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Cursor, Read, Seek, SeekFrom, Write};
+use std::mem::ManuallyDrop;
+use std::os::raw::c_int;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use zip::read::ZipFile;
-use zip::ZipArchive;
+use zip::result::{InvalidPassword, ZipError, ZipResult};
+use zip::write::FileOptions;
+use zip::{CompressionMethod, DateTime, ZipArchive, ZipWriter};
+
+/// Zip data held in memory rather than read from a file.
+#[derive(Clone)]
+pub enum ArchiveBytes {
+    Owned(Arc<[u8]>),
+    Mapped(Arc<Mmap>),
+    // owned by the host, which keeps it alive and unchanged for as long as
+    // the archive (or anything opened from it) exists
+    Borrowed(*const u8, usize),
+    // a window onto other bytes, e.g. a stored zip inside another zip
+    Slice(Box<ArchiveBytes>, usize, usize),
+}
+
+// Safety: the bytes are only ever read, and borrowed bytes outlive every
+// reader by the host's contract (see trussfs_archive_mount_memory)
+unsafe impl Send for ArchiveBytes {}
+
+impl ArchiveBytes {
+    fn is_borrowed(&self) -> bool {
+        match self {
+            ArchiveBytes::Borrowed(..) => true,
+            ArchiveBytes::Slice(bytes, ..) => bytes.is_borrowed(),
+            _ => false,
+        }
+    }
+}
+
+impl AsRef<[u8]> for ArchiveBytes {
+    fn as_ref(&self) -> &[u8] {
+        match self {
+            ArchiveBytes::Owned(data) => data,
+            ArchiveBytes::Mapped(map) => map,
+            ArchiveBytes::Borrowed(ptr, len) => unsafe { std::slice::from_raw_parts(*ptr, *len) },
+            ArchiveBytes::Slice(bytes, start, len) => &(**bytes).as_ref()[*start..*start + *len],
+        }
+    }
+}
+
+/// A range of an open file, read at explicit offsets rather than through
+/// the file's own cursor, so clones share the open file (and keep reading
+/// the same version of it even if it is replaced on disk) without sharing
+/// a seek position.
+#[derive(Clone)]
+pub struct FileWindow {
+    file: Arc<File>,
+    start: u64,
+    len: u64,
+    // relative to `start`
+    pos: u64,
+}
+
+impl FileWindow {
+    fn new(file: File) -> std::io::Result<Self> {
+        let len = file.metadata()?.len();
+        Ok(FileWindow {
+            file: Arc::new(file),
+            start: 0,
+            len,
+            pos: 0,
+        })
+    }
+}
+
+impl FileWindow {
+    // `len` bytes of this window from `start`, sharing the open file
+    fn window(&self, start: u64, len: u64) -> Result<Self, String> {
+        match start.checked_add(len) {
+            Some(end) if end <= self.len => Ok(FileWindow {
+                file: self.file.clone(),
+                start: self.start + start,
+                len,
+                pos: 0,
+            }),
+            _ => Err(String::from("Entry data out of bounds")),
+        }
+    }
+}
+
+impl Read for FileWindow {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let remaining = self.len.saturating_sub(self.pos);
+        let want = buf.len().min(remaining.min(usize::MAX as u64) as usize);
+        let offset = self.start + self.pos;
+        #[cfg(unix)]
+        let n = std::os::unix::fs::FileExt::read_at(&*self.file, &mut buf[..want], offset)?;
+        #[cfg(windows)]
+        let n = std::os::windows::fs::FileExt::seek_read(&*self.file, &mut buf[..want], offset)?;
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+
+impl Seek for FileWindow {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        let target = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::Current(delta) => self.pos.checked_add_signed(delta),
+            SeekFrom::End(delta) => self.len.checked_add_signed(delta),
+        };
+        match target {
+            Some(target) => {
+                self.pos = target;
+                Ok(target)
+            }
+            None => Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "invalid seek to a negative or overflowing position",
+            )),
+        }
+    }
+}
+
+pub enum ZipSource {
+    File(BufReader<FileWindow>),
+    Memory(Cursor<ArchiveBytes>),
+}
+
+// Cloning gives an independent cursor over the same data; the position
+// needn't carry over, since the zip reader seeks before every entry.
+impl Clone for ZipSource {
+    fn clone(&self) -> Self {
+        match self {
+            ZipSource::File(file) => ZipSource::File(BufReader::new(file.get_ref().clone())),
+            ZipSource::Memory(cursor) => ZipSource::Memory(Cursor::new(cursor.get_ref().clone())),
+        }
+    }
+}
+
+impl Read for ZipSource {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match self {
+            ZipSource::File(file) => file.read(buf),
+            ZipSource::Memory(cursor) => cursor.read(buf),
+        }
+    }
+}
+
+impl Seek for ZipSource {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        match self {
+            ZipSource::File(file) => file.seek(pos),
+            ZipSource::Memory(cursor) => cursor.seek(pos),
+        }
+    }
+}
+
+pub type ZipReader = ZipArchive<ZipSource>;
+
+// Where an archive's bytes come from
+enum Origin {
+    File(String),
+    Mapped(String, Arc<Mmap>),
+    Memory(ArchiveBytes),
+    // a stored zip inside a file-backed archive, read in place
+    Window(FileWindow),
+}
 
 pub struct Archive {
-    zip: ZipArchive<BufReader<File>>,
+    origin: Origin,
+    zip: ZipReader,
+    index: DirIndex,
+    // reopen `zip` whenever the file's stamp changes
+    auto_reload: bool,
+    stamp: Option<FileStamp>,
+    // number of times `zip` has been reopened
+    generation: u64,
+    password: Option<Vec<u8>>,
+    // entry name -> index, built on first use and dropped on reload
+    names: Option<HashMap<String, usize>>,
+}
+
+/// Central directory metadata for one entry. Entries whose names would
+/// escape the extraction directory (absolute, "..") are still listed, with
+/// their raw name and `is_unsafe` set.
+pub struct ArchiveEntry {
+    pub index: u64,
+    pub name: CString,
+    pub comment: CString,
+    pub is_dir: bool,
+    pub is_unsafe: bool,
+    pub is_encrypted: bool,
+    pub size: u64,
+    pub compressed_size: u64,
+    // raw zip method code, e.g. 0 = stored, 8 = deflate
+    pub compression: u32,
+    pub crc32: u32,
+    // seconds since the Unix epoch; zip times carry no timezone, so this
+    // reads them as local time, like copy jobs do
+    pub mtime: f64,
+    // 0 if the archive wasn't made on a unix-like system
+    pub unix_mode: u32,
+}
+
+/// Directory tree over the entry names, covering both explicit directory
+/// entries and the directories implied by the paths of other entries.
+/// Paths are normalized like virtual paths (empty and "." segments
+/// dropped); entries with absolute or ".." paths are left out.
+#[derive(Default)]
+struct DirIndex {
+    // directory ("" for the root) -> child name -> node
+    dirs: HashMap<String, BTreeMap<String, IndexNode>>,
+}
+
+// A name can be both a file and a directory (e.g. entries "same" and
+// "same/x"), so the two are tracked separately.
+#[derive(Default)]
+struct IndexNode {
+    // raw name of the file entry, which is what the zip is read by
+    file: Option<String>,
+    is_dir: bool,
+}
+
+impl DirIndex {
+    fn build<'a>(names: impl Iterator<Item = &'a str>) -> Self {
+        let mut dirs: HashMap<String, BTreeMap<String, IndexNode>> = HashMap::new();
+        dirs.insert(String::new(), BTreeMap::new());
+        for name in names {
+            let parts: Vec<&str> = name
+                .split(['/', '\\'])
+                .filter(|part| !part.is_empty() && *part != ".")
+                .collect();
+            if name.starts_with(['/', '\\']) || parts.is_empty() || parts.contains(&"..") {
+                continue;
+            }
+            let is_dir_entry = name.ends_with(['/', '\\']);
+            let mut dir = String::new();
+            for (i, part) in parts.iter().enumerate() {
+                let is_dir = i + 1 < parts.len() || is_dir_entry;
+                let node = dirs
+                    .entry(dir.clone())
+                    .or_default()
+                    .entry(part.to_string())
+                    .or_default();
+                if is_dir {
+                    node.is_dir = true;
+                } else {
+                    node.file = Some(name.to_string());
+                }
+                if !dir.is_empty() {
+                    dir.push('/');
+                }
+                dir.push_str(part);
+                if is_dir {
+                    dirs.entry(dir.clone()).or_default();
+                }
+            }
+        }
+        DirIndex { dirs }
+    }
+
+    fn is_dir(&self, path: &str) -> bool {
+        self.dirs.contains_key(path)
+    }
+
+    /// Raw entry name of the file at a normalized path.
+    fn file_entry(&self, path: &str) -> Option<&str> {
+        let (dir, name) = path.rsplit_once('/').unwrap_or(("", path));
+        self.dirs.get(dir)?.get(name)?.file.as_deref()
+    }
+
+    fn is_file(&self, path: &str) -> bool {
+        self.file_entry(path).is_some()
+    }
+
+    fn children(&self, path: &str) -> Option<&BTreeMap<String, IndexNode>> {
+        self.dirs.get(path)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+struct FileStamp {
+    mtime: Option<SystemTime>,
+    size: u64,
+}
+
+fn file_stamp(path: &str) -> Option<FileStamp> {
+    let metadata = fs::metadata(path).ok()?;
+    Some(FileStamp {
+        mtime: metadata.modified().ok(),
+        size: metadata.len(),
+    })
+}
+
+/// Streams a single entry out of an archive. The reader owns a separate
+/// view of the zip data, so it stays independent of the `Archive` it
+/// was opened from (which may be freed while the reader is still open).
+pub struct EntryReader {
+    // Really borrows from `*zip` rather than being 'static. This is sound
+    // as long as:
+    // - `zip` is a heap allocation owned by this reader, which is neither
+    //   moved nor touched (other than through `file`) while `file` exists
+    // - `file` is dropped before `zip` is freed, which `drop` does
+    //   explicitly (field order alone isn't relied on, hence ManuallyDrop)
+    // - `file` never leaves this struct with its 'static lifetime
+    file: ManuallyDrop<ZipFile<'static>>,
+    zip: *mut ZipReader,
+}
+
+pub struct ArchiveWriter {
+    zip: ZipWriter<BufWriter<File>>,
 }
 
 fn format_zip_file_entry(idx: usize, file: &ZipFile) -> CString {
@@ -34,6 +342,107 @@ fn format_zip_file_entry(idx: usize, file: &ZipFile) -> CString {
     CString::new(s).unwrap()
 }
 
+#[cfg(windows)]
+extern "C" {
+    fn _mktime64(tm: *mut libc::tm) -> i64;
+}
+
+// Zip timestamps are in the local time of whoever made the zip, which is
+// taken to be ours; convert through mktime so they compare correctly with
+// filesystem times.
+pub fn zip_local_time(time: DateTime) -> Option<SystemTime> {
+    // Safety: tm is plain data, all zeroes is a valid value
+    let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+    tm.tm_year = time.year() as c_int - 1900;
+    tm.tm_mon = time.month() as c_int - 1;
+    tm.tm_mday = time.day() as c_int;
+    tm.tm_hour = time.hour() as c_int;
+    tm.tm_min = time.minute() as c_int;
+    tm.tm_sec = time.second() as c_int;
+    // let mktime work out whether daylight saving applies
+    tm.tm_isdst = -1;
+    #[cfg(unix)]
+    let secs = unsafe { libc::mktime(&mut tm) } as i64;
+    #[cfg(windows)]
+    let secs = unsafe { _mktime64(&mut tm) };
+    match secs {
+        -1 => None,
+        secs if secs >= 0 => UNIX_EPOCH.checked_add(Duration::from_secs(secs as u64)),
+        secs => UNIX_EPOCH.checked_sub(Duration::from_secs(secs.unsigned_abs())),
+    }
+}
+
+fn zip_file_entry(index: usize, file: &ZipFile) -> ArchiveEntry {
+    let mtime = match zip_local_time(file.last_modified()).map(|t| t.duration_since(UNIX_EPOCH)) {
+        Some(Ok(duration)) => duration.as_secs_f64(),
+        _ => 0.0,
+    };
+    #[allow(deprecated)] // the named constants can't express the raw code
+    let compression = file.compression().to_u16() as u32;
+    ArchiveEntry {
+        index: index as u64,
+        name: CString::new(file.name()).unwrap_or_default(),
+        comment: CString::new(file.comment()).unwrap_or_default(),
+        is_dir: file.is_dir(),
+        is_unsafe: file.enclosed_name().is_none(),
+        // filled in from the central directory by `list_entries`
+        is_encrypted: false,
+        size: file.size(),
+        compressed_size: file.compressed_size(),
+        compression,
+        crc32: file.crc32(),
+        mtime,
+        unix_mode: file.unix_mode().unwrap_or(0),
+    }
+}
+
+fn password_required<T>(result: &ZipResult<T>) -> bool {
+    matches!(
+        result,
+        Err(ZipError::UnsupportedArchive(ZipError::PASSWORD_REQUIRED))
+    )
+}
+
+fn decrypted<'a>(
+    name: &str,
+    file: ZipResult<Result<ZipFile<'a>, InvalidPassword>>,
+) -> Result<ZipFile<'a>, String> {
+    match file {
+        Ok(Ok(file)) => Ok(file),
+        Ok(Err(InvalidPassword)) => Err(format!("Wrong password for {}", name)),
+        Err(ZipError::UnsupportedArchive(ZipError::PASSWORD_REQUIRED)) => {
+            Err(format!("Password required for {}", name))
+        }
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+/// Look up an entry for reading, decrypting it with `password` if the entry
+/// is encrypted (the password is ignored otherwise).
+pub fn zip_by_name<'a>(
+    zip: &'a mut ZipReader,
+    name: &str,
+    password: Option<&[u8]>,
+) -> Result<ZipFile<'a>, String> {
+    let file = match password {
+        Some(password) => zip.by_name_decrypt(name, password),
+        None => zip.by_name(name).map(Ok),
+    };
+    decrypted(name, file)
+}
+
+pub fn zip_by_index<'a>(
+    zip: &'a mut ZipReader,
+    index: usize,
+    password: Option<&[u8]>,
+) -> Result<ZipFile<'a>, String> {
+    let file = match password {
+        Some(password) => zip.by_index_decrypt(index, password),
+        None => zip.by_index(index).map(Ok),
+    };
+    decrypted(&format!("entry {}", index), file)
+}
+
 fn read_zip_file(file: &mut ZipFile) -> Result<Vec<u8>, String> {
     let mut dest: Vec<u8> = Vec::with_capacity(file.compressed_size() as usize);
     match file.read_to_end(&mut dest) {
@@ -42,42 +451,502 @@ fn read_zip_file(file: &mut ZipFile) -> Result<Vec<u8>, String> {
     }
 }
 
+fn open_zip(filename: &str) -> Result<ZipReader, String> {
+    let file = File::open(filename).map_err(|e| e.to_string())?;
+    let window = FileWindow::new(file).map_err(|e| e.to_string())?;
+    open_window(window)
+}
+
+fn open_window(window: FileWindow) -> Result<ZipReader, String> {
+    ZipArchive::new(ZipSource::File(BufReader::new(window))).map_err(|e| e.to_string())
+}
+
+fn map_file(filename: &str) -> Result<Arc<Mmap>, String> {
+    let file = File::open(filename).map_err(|e| e.to_string())?;
+    // Safety: undefined if the file is truncated or rewritten in place while
+    // mapped; documented as a requirement of mapped archives
+    let map = unsafe { Mmap::map(&file) }.map_err(|e| e.to_string())?;
+    Ok(Arc::new(map))
+}
+
+fn open_bytes(bytes: ArchiveBytes) -> Result<ZipReader, String> {
+    ZipArchive::new(ZipSource::Memory(Cursor::new(bytes))).map_err(|e| e.to_string())
+}
+
+// `len` bytes of `bytes` from `start`, without nesting slices of slices
+fn slice_bytes(bytes: ArchiveBytes, start: u64, len: u64) -> Result<ArchiveBytes, String> {
+    match start.checked_add(len) {
+        Some(end) if end <= bytes.as_ref().len() as u64 => {}
+        _ => return Err(String::from("Entry data out of bounds")),
+    }
+    let (start, len) = (start as usize, len as usize);
+    Ok(match bytes {
+        ArchiveBytes::Slice(inner, offset, _) => ArchiveBytes::Slice(inner, offset + start, len),
+        bytes => ArchiveBytes::Slice(Box::new(bytes), start, len),
+    })
+}
+
+impl Origin {
+    fn path(&self) -> Option<&str> {
+        match self {
+            Origin::File(path) | Origin::Mapped(path, _) => Some(path),
+            Origin::Memory(_) | Origin::Window(_) => None,
+        }
+    }
+
+    fn open(&self) -> Result<ZipReader, String> {
+        match self {
+            Origin::File(path) => open_zip(path),
+            Origin::Mapped(_, map) => open_bytes(ArchiveBytes::Mapped(map.clone())),
+            Origin::Memory(bytes) => open_bytes(bytes.clone()),
+            Origin::Window(window) => open_window(window.clone()),
+        }
+    }
+}
+
+impl EntryReader {
+    fn open(zip: ZipReader, name: &str, password: Option<&[u8]>) -> Result<Self, String> {
+        let zip = Box::into_raw(Box::new(zip));
+        // Safety: see the invariants on `EntryReader::file`; the archive
+        // lives on the heap until `drop` frees it, after the entry.
+        let file = match zip_by_name(unsafe { &mut *zip }, name, password) {
+            Ok(file) => unsafe { std::mem::transmute::<ZipFile<'_>, ZipFile<'static>>(file) },
+            Err(e) => {
+                drop(unsafe { Box::from_raw(zip) });
+                return Err(e);
+            }
+        };
+        Ok(EntryReader {
+            file: ManuallyDrop::new(file),
+            zip,
+        })
+    }
+
+    /// Read until `dest` is full or the entry is exhausted; returns the
+    /// number of bytes read, which is only short at the end of the entry.
+    pub fn read(&mut self, dest: &mut [u8]) -> Result<usize, String> {
+        let mut total = 0;
+        while total < dest.len() {
+            match self.file.read(&mut dest[total..]) {
+                Ok(0) => break,
+                Ok(n) => total += n,
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e.to_string()),
+            }
+        }
+        Ok(total)
+    }
+}
+
+impl Drop for EntryReader {
+    fn drop(&mut self) {
+        unsafe {
+            ManuallyDrop::drop(&mut self.file);
+            drop(Box::from_raw(self.zip));
+        }
+    }
+}
+
 impl Archive {
-    pub fn open(filename: String) -> Result<Self, String> {
-        let file = File::open(filename).map_err(|e| e.to_string())?;
-        let reader = BufReader::new(file);
+    fn from_origin(origin: Origin, stamp: Option<FileStamp>) -> Result<Self, String> {
+        let zip = origin.open()?;
         Ok(Archive {
-            zip: ZipArchive::new(reader).map_err(|e| e.to_string())?,
+            origin,
+            index: DirIndex::build(zip.file_names()),
+            zip,
+            auto_reload: false,
+            stamp,
+            generation: 0,
+            password: None,
+            names: None,
         })
     }
 
+    pub fn open(filename: String) -> Result<Self, String> {
+        // stamp before opening, so a change that races the open is caught
+        // by the next refresh rather than missed
+        let stamp = file_stamp(&filename);
+        Self::from_origin(Origin::File(filename), stamp)
+    }
+
+    /// Like `open`, but the whole file is memory mapped, which makes
+    /// reopening (for readers and copies) free and random access cheap.
+    pub fn open_mapped(filename: String) -> Result<Self, String> {
+        let stamp = file_stamp(&filename);
+        let map = map_file(&filename)?;
+        Self::from_origin(Origin::Mapped(filename, map), stamp)
+    }
+
+    pub fn from_memory(bytes: ArchiveBytes) -> Result<Self, String> {
+        Self::from_origin(Origin::Memory(bytes), None)
+    }
+
+    /// Open a zip stored inside this one as an archive of its own. A stored
+    /// (uncompressed) entry is read in place, sharing this archive's open
+    /// file or memory; anything else is decompressed into a buffer. Either
+    /// way the nested archive stays valid after this one is freed. It
+    /// starts out with this archive's password.
+    pub fn open_nested(&mut self, filename: String) -> Result<Self, String> {
+        self.refresh();
+        let password = self.password.clone();
+        // encrypted entries can't be used in place, even when stored
+        let encrypted = password_required(&self.zip.by_name(&filename));
+        let (stored, start, len) = {
+            let file = zip_by_name(&mut self.zip, &filename, password.as_deref())?;
+            let stored = !encrypted && file.compression() == CompressionMethod::Stored;
+            (stored, file.data_start(), file.size())
+        };
+        let in_place = match stored {
+            true => self.stored_origin(start, len),
+            false => Ok(None),
+        };
+        let origin = match in_place {
+            Ok(Some(origin)) => Ok(origin),
+            Ok(None) => {
+                let mut file = zip_by_name(&mut self.zip, &filename, password.as_deref())?;
+                read_zip_file(&mut file)
+                    .map(|data| Origin::Memory(ArchiveBytes::Owned(data.into())))
+            }
+            Err(e) => Err(e),
+        };
+        let mut archive = origin
+            .and_then(|origin| Self::from_origin(origin, None))
+            .map_err(|e| format!("{}: {}", filename, e))?;
+        archive.password = password;
+        Ok(archive)
+    }
+
+    // Where a stored entry's data can be read in place, if it can
+    fn stored_origin(&self, start: u64, len: u64) -> Result<Option<Origin>, String> {
+        match self.reopen().into_inner() {
+            ZipSource::File(file) => Ok(Some(Origin::Window(file.get_ref().window(start, len)?))),
+            // host memory is only valid as long as the host keeps it, which
+            // may be no longer than this archive; copy it instead
+            ZipSource::Memory(cursor) if cursor.get_ref().is_borrowed() => Ok(None),
+            ZipSource::Memory(cursor) => Ok(Some(Origin::Memory(slice_bytes(
+                cursor.into_inner(),
+                start,
+                len,
+            )?))),
+        }
+    }
+
+    /// The zip file on disk, which in-memory archives don't have.
+    pub fn source_file(&self) -> Result<PathBuf, String> {
+        match self.origin.path() {
+            Some(path) => Ok(PathBuf::from(path)),
+            None => Err(String::from(
+                "Archive has no source file (in-memory or nested)",
+            )),
+        }
+    }
+
+    pub fn set_auto_reload(&mut self, enabled: bool) {
+        self.auto_reload = enabled;
+    }
+
+    /// Password for reading encrypted (ZipCrypto or AES) entries; None to
+    /// clear it. Entries that aren't encrypted are unaffected.
+    pub fn set_password(&mut self, password: Option<Vec<u8>>) {
+        self.password = password;
+    }
+
+    pub fn password(&self) -> Option<&[u8]> {
+        self.password.as_deref()
+    }
+
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// With auto-reload on, reopen the zip if the file's mtime or size has
+    /// changed since it was last opened; returns whether it was reopened.
+    /// If the new file can't be opened (e.g. it is still being written) the
+    /// error is logged, the old view is kept (so reads keep working) and
+    /// the reload is retried on the next refresh.
+    pub fn refresh(&mut self) -> bool {
+        match self.try_refresh() {
+            Ok(reloaded) => reloaded,
+            Err(e) => {
+                warn!("{}", e);
+                false
+            }
+        }
+    }
+
+    fn try_refresh(&mut self) -> Result<bool, String> {
+        let path = match self.origin.path() {
+            Some(path) if self.auto_reload => path.to_string(),
+            _ => return Ok(false),
+        };
+        let stamp = file_stamp(&path);
+        if stamp == self.stamp {
+            return Ok(false);
+        }
+        let reload = |origin: &Origin| -> Result<(ZipReader, Option<Arc<Mmap>>), String> {
+            match origin {
+                Origin::Mapped(..) => {
+                    let map = map_file(&path)?;
+                    Ok((open_bytes(ArchiveBytes::Mapped(map.clone()))?, Some(map)))
+                }
+                _ => Ok((origin.open()?, None)),
+            }
+        };
+        let (zip, new_map) =
+            reload(&self.origin).map_err(|e| format!("Reloading {}: {}", path, e))?;
+        if let (Origin::Mapped(_, map), Some(new_map)) = (&mut self.origin, new_map) {
+            *map = new_map;
+        }
+        self.zip = zip;
+        self.index = DirIndex::build(self.zip.file_names());
+        self.names = None;
+        self.stamp = stamp;
+        self.generation += 1;
+        info!("Reloaded {} (generation {})", path, self.generation);
+        Ok(true)
+    }
+
+    /// A second, independent view of the same zip (e.g. to hand off to a
+    /// reader or another thread). It shares the parsed central directory and
+    /// the open file or bytes, so it is cheap, and it keeps reading the
+    /// version of the zip this archive has open now even if that is later
+    /// reloaded.
+    pub fn reopen(&self) -> ZipReader {
+        self.zip.clone()
+    }
+
+    pub fn open_entry(&mut self, filename: String) -> Result<EntryReader, String> {
+        self.refresh();
+        EntryReader::open(self.reopen(), &filename, self.password())
+    }
+
     pub fn list_files(&mut self) -> StringList {
+        self.refresh();
         let mut filelist: StringList = Vec::new();
         for i in 0..self.zip.len() {
-            if let Ok(file) = self.zip.by_index(i) {
+            if let Ok(file) = self.zip.by_index_raw(i) {
                 filelist.push(format_zip_file_entry(i, &file));
             };
         }
         filelist
     }
 
+    /// Typed version of `list_files`; entries are read raw, so encrypted or
+    /// unsupported entries are listed too.
+    pub fn list_entries(&mut self) -> Vec<ArchiveEntry> {
+        self.refresh();
+        let mut entries: Vec<ArchiveEntry> = Vec::new();
+        let mut headers: Vec<u64> = Vec::new();
+        for i in 0..self.zip.len() {
+            if let Ok(file) = self.zip.by_index_raw(i) {
+                entries.push(zip_file_entry(i, &file));
+                headers.push(file.central_header_start());
+            }
+        }
+        for (entry, encrypted) in entries.iter_mut().zip(self.encrypted_flags(&headers)) {
+            entry.is_encrypted = encrypted;
+        }
+        entries
+    }
+
+    // Bit 0 of each entry's general purpose flags, which zip doesn't expose;
+    // the central directory records are read in one pass.
+    fn encrypted_flags(&self, headers: &[u64]) -> Vec<bool> {
+        let base = match headers.iter().min() {
+            Some(&base) => base,
+            None => return Vec::new(),
+        };
+        let mut source = self.reopen().into_inner();
+        let mut dir: Vec<u8> = Vec::new();
+        let read = source
+            .seek(SeekFrom::Start(base))
+            .and_then(|_| source.read_to_end(&mut dir));
+        if read.is_err() {
+            return vec![false; headers.len()];
+        }
+        headers
+            .iter()
+            .map(|&start| {
+                let flags = (start - base) as usize + 8;
+                matches!(dir.get(flags..flags + 2), Some(bytes) if bytes[0] & 1 != 0)
+            })
+            .collect()
+    }
+
+    fn entry_index(&mut self, name: &str) -> Result<usize, String> {
+        if self.names.is_none() {
+            let mut names: HashMap<String, usize> = HashMap::new();
+            for i in 0..self.zip.len() {
+                if let Ok(file) = self.zip.by_index_raw(i) {
+                    names.insert(file.name().to_string(), i);
+                }
+            }
+            self.names = Some(names);
+        }
+        match self.names.as_ref().and_then(|names| names.get(name)) {
+            Some(&index) => Ok(index),
+            None => Err(ZipError::FileNotFound.to_string()),
+        }
+    }
+
+    // Sizes come from the central directory, so no password is needed.
     pub fn filesize_by_index(&mut self, index: usize) -> Result<u64, String> {
-        let file = self.zip.by_index(index).map_err(|e| e.to_string())?;
-        Ok(file.size())
+        self.refresh();
+        match self.zip.by_index_raw(index) {
+            Ok(file) => Ok(file.size()),
+            Err(e) => Err(e.to_string()),
+        }
     }
 
     pub fn filesize_by_name(&mut self, filename: String) -> Result<u64, String> {
-        let file = self.zip.by_name(&filename).map_err(|e| e.to_string())?;
-        Ok(file.size())
+        self.refresh();
+        // by_name refuses encrypted entries without their password, and
+        // zip has no raw lookup by name; fall back to the index
+        let index = match self.zip.by_name(&filename).map(|file| file.size()) {
+            Ok(size) => return Ok(size),
+            Err(ZipError::UnsupportedArchive(ZipError::PASSWORD_REQUIRED)) => {
+                self.entry_index(&filename)?
+            }
+            Err(e) => return Err(e.to_string()),
+        };
+        match self.zip.by_index_raw(index) {
+            Ok(file) => Ok(file.size()),
+            Err(e) => Err(e.to_string()),
+        }
     }
 
     pub fn read_file_by_index(&mut self, index: usize) -> Result<Vec<u8>, String> {
-        let mut file = self.zip.by_index(index).map_err(|e| e.to_string())?;
+        self.refresh();
+        let mut file = zip_by_index(&mut self.zip, index, self.password.as_deref())?;
         read_zip_file(&mut file)
     }
 
     pub fn read_file_by_name(&mut self, filename: String) -> Result<Vec<u8>, String> {
-        let mut file = self.zip.by_name(&filename).map_err(|e| e.to_string())?;
+        self.refresh();
+        let mut file = zip_by_name(&mut self.zip, &filename, self.password.as_deref())?;
         read_zip_file(&mut file)
     }
+
+    // Raw entry name for a path as the directory index sees it
+    fn indexed_entry(&self, path: &str) -> Result<String, String> {
+        let path = normalize_vpath(path)?;
+        match self.index.file_entry(&path) {
+            Some(name) => Ok(name.to_string()),
+            None => Err(format!("No such file in archive: {}", path)),
+        }
+    }
+
+    /// Like `read_file_by_name`, but `path` is looked up through the
+    /// directory index, so it resolves exactly as `is_file` and `list_dir`
+    /// report it.
+    pub fn read_file_by_path(&mut self, path: &str) -> Result<Vec<u8>, String> {
+        self.refresh();
+        let name = self.indexed_entry(path)?;
+        self.read_file_by_name(name)
+    }
+
+    pub fn filesize_by_path(&mut self, path: &str) -> Result<u64, String> {
+        self.refresh();
+        let name = self.indexed_entry(path)?;
+        self.filesize_by_name(name)
+    }
+
+    pub fn is_file(&self, filename: &str) -> bool {
+        self.index.is_file(filename)
+    }
+
+    pub fn is_dir(&self, dirname: &str) -> bool {
+        self.index.is_dir(dirname)
+    }
+
+    /// Immediate children of a directory as (name, is_dir) pairs, sorted;
+    /// directories that only exist implicitly (as a prefix of some entry)
+    /// are included, and a name that is both a file and a directory appears
+    /// once as each.
+    pub fn dir_children(&self, dirname: &str) -> Vec<(String, bool)> {
+        let mut result = Vec::new();
+        for (name, node) in self.index.children(dirname).into_iter().flatten() {
+            if node.file.is_some() {
+                result.push((name.clone(), false));
+            }
+            if node.is_dir {
+                result.push((name.clone(), true));
+            }
+        }
+        result
+    }
+
+    /// Names in a directory of the archive, like `trussfs_list_dir`; `dir`
+    /// is "a/b" style, with "" (or "/") for the root.
+    pub fn list_dir(&mut self, dir: &str, files_only: bool) -> Result<Vec<String>, String> {
+        self.refresh();
+        let dir = normalize_vpath(dir)?;
+        match self.index.children(&dir) {
+            Some(children) => Ok(children
+                .iter()
+                .filter(|(_, node)| !files_only || node.file.is_some())
+                .map(|(name, _)| name.clone())
+                .collect()),
+            None => Err(format!("No such directory in archive: {}", dir)),
+        }
+    }
+
+    /// Whether `path` is a file or (possibly implicit) directory.
+    pub fn exists(&mut self, path: &str) -> bool {
+        self.refresh();
+        match normalize_vpath(path) {
+            Ok(path) => self.index.is_file(&path) || self.index.is_dir(&path),
+            Err(_) => false,
+        }
+    }
+}
+
+// Negative levels use the default deflate level, 0 stores without
+// compression, and 1-9 are passed through to deflate. Other levels are
+// rejected here: zip only checks them after writing the local header,
+// which leaves the writer unusable.
+fn compression_options(level: i32) -> Result<FileOptions, String> {
+    let options = FileOptions::default();
+    match level {
+        level if level < 0 => Ok(options.compression_method(CompressionMethod::Deflated)),
+        0 => Ok(options.compression_method(CompressionMethod::Stored)),
+        1..=9 => Ok(options
+            .compression_method(CompressionMethod::Deflated)
+            .compression_level(Some(level))),
+        _ => Err(format!("Unsupported compression level: {}", level)),
+    }
+}
+
+impl ArchiveWriter {
+    pub fn create(filename: String) -> Result<Self, String> {
+        let file = File::create(filename).map_err(|e| e.to_string())?;
+        Ok(ArchiveWriter {
+            zip: ZipWriter::new(BufWriter::new(file)),
+        })
+    }
+
+    pub fn add_file(
+        &mut self,
+        filename: String,
+        data: &[u8],
+        compression_level: i32,
+    ) -> Result<(), String> {
+        let options =
+            compression_options(compression_level)?.large_file(data.len() >= u32::MAX as usize);
+        self.zip
+            .start_file(filename, options)
+            .map_err(|e| e.to_string())?;
+        self.zip.write_all(data).map_err(|e| e.to_string())
+    }
+
+    pub fn add_dir(&mut self, dirname: String) -> Result<(), String> {
+        self.zip
+            .add_directory(dirname, FileOptions::default())
+            .map_err(|e| e.to_string())
+    }
+
+    pub fn finish(mut self) -> Result<(), String> {
+        let mut writer = self.zip.finish().map_err(|e| e.to_string())?;
+        writer.flush().map_err(|e| e.to_string())
+    }
 }
@@ -1,24 +1,75 @@
-use crate::archive::Archive;
-use crate::watcher::FileWatcher;
-use slotmap::{HopSlotMap, Key};
+use crate::archive::{Archive, ArchiveEntry, ArchiveWriter, EntryReader};
+use crate::copyjob::{CopyJob, CopySource, JobProgress};
+use crate::dirent::DirEntryList;
+use crate::fileio;
+use crate::reload::ReloadTracker;
+use crate::vfs::{MountSource, Vfs};
+use crate::walk::{walk_dir, WalkOptions};
+use crate::watcher::{
+    EventList, FileWatcher, PathFilter, WakeSignal, WatchCallback, WatcherOptions, BACKEND_AUTO,
+    BACKEND_POLL, WAIT_ERROR, WAIT_TIMEOUT, WATCHER_KIND_POLL,
+};
+use log::warn;
+use slotmap::Key;
 use std::convert::From;
 use std::env::{current_dir, current_exe};
 use std::ffi::CString;
 use std::fs;
-use std::path::Path;
+use std::os::raw::c_void;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 pub type StringList = Vec<CString>;
 
+// Every handle type lives in a HopSlotMap. It is deprecated upstream, but
+// switching maps would change how freed handles get reused, so the allow
+// is kept here rather than at every use.
+#[allow(deprecated)]
+pub type HandleMap<K, V> = slotmap::HopSlotMap<K, V>;
+
 slotmap::new_key_type! {
   pub struct ArchiveKey;
 }
+slotmap::new_key_type! {
+  pub struct ArchiveWriterKey;
+}
 slotmap::new_key_type! {
   pub struct StringListKey;
 }
+slotmap::new_key_type! {
+  pub struct DirListKey;
+}
+slotmap::new_key_type! {
+  pub struct StringKey;
+}
 
 slotmap::new_key_type! {
   pub struct WatcherKey;
 }
+slotmap::new_key_type! {
+  pub struct EventListKey;
+}
+
+slotmap::new_key_type! {
+  pub struct EntryListKey;
+}
+
+slotmap::new_key_type! {
+  pub struct MountKey;
+}
+
+slotmap::new_key_type! {
+  pub struct ReaderKey;
+}
+
+slotmap::new_key_type! {
+  pub struct JobKey;
+}
+
+slotmap::new_key_type! {
+  pub struct ReloadKey;
+}
 
 // Eh, couldn't figure out how to make this generic
 impl From<u64> for ArchiveKey {
@@ -32,6 +83,17 @@ impl From<ArchiveKey> for u64 {
     }
 }
 
+impl From<u64> for ArchiveWriterKey {
+    fn from(item: u64) -> Self {
+        Self::from(slotmap::KeyData::from_ffi(item))
+    }
+}
+impl From<ArchiveWriterKey> for u64 {
+    fn from(item: ArchiveWriterKey) -> Self {
+        item.data().as_ffi()
+    }
+}
+
 impl From<u64> for WatcherKey {
     fn from(item: u64) -> Self {
         Self::from(slotmap::KeyData::from_ffi(item))
@@ -43,6 +105,94 @@ impl From<WatcherKey> for u64 {
     }
 }
 
+impl From<u64> for EventListKey {
+    fn from(item: u64) -> Self {
+        Self::from(slotmap::KeyData::from_ffi(item))
+    }
+}
+impl From<EventListKey> for u64 {
+    fn from(item: EventListKey) -> Self {
+        item.data().as_ffi()
+    }
+}
+
+impl From<u64> for MountKey {
+    fn from(item: u64) -> Self {
+        Self::from(slotmap::KeyData::from_ffi(item))
+    }
+}
+impl From<MountKey> for u64 {
+    fn from(item: MountKey) -> Self {
+        item.data().as_ffi()
+    }
+}
+
+impl From<u64> for ReaderKey {
+    fn from(item: u64) -> Self {
+        Self::from(slotmap::KeyData::from_ffi(item))
+    }
+}
+impl From<ReaderKey> for u64 {
+    fn from(item: ReaderKey) -> Self {
+        item.data().as_ffi()
+    }
+}
+
+impl From<u64> for DirListKey {
+    fn from(item: u64) -> Self {
+        Self::from(slotmap::KeyData::from_ffi(item))
+    }
+}
+impl From<DirListKey> for u64 {
+    fn from(item: DirListKey) -> Self {
+        item.data().as_ffi()
+    }
+}
+
+impl From<u64> for JobKey {
+    fn from(item: u64) -> Self {
+        Self::from(slotmap::KeyData::from_ffi(item))
+    }
+}
+impl From<JobKey> for u64 {
+    fn from(item: JobKey) -> Self {
+        item.data().as_ffi()
+    }
+}
+
+impl From<u64> for ReloadKey {
+    fn from(item: u64) -> Self {
+        Self::from(slotmap::KeyData::from_ffi(item))
+    }
+}
+impl From<ReloadKey> for u64 {
+    fn from(item: ReloadKey) -> Self {
+        item.data().as_ffi()
+    }
+}
+
+impl From<u64> for EntryListKey {
+    fn from(item: u64) -> Self {
+        Self::from(slotmap::KeyData::from_ffi(item))
+    }
+}
+impl From<EntryListKey> for u64 {
+    fn from(item: EntryListKey) -> Self {
+        item.data().as_ffi()
+    }
+}
+
+impl From<u64> for StringKey {
+    fn from(item: u64) -> Self {
+        Self::from(slotmap::KeyData::from_ffi(item))
+    }
+}
+impl From<StringKey> for u64 {
+    fn from(item: StringKey) -> Self {
+        item.data().as_ffi()
+    }
+}
+
 impl From<u64> for StringListKey {
     fn from(item: u64) -> Self {
         Self::from(slotmap::KeyData::from_ffi(item))
@@ -58,9 +208,19 @@ pub struct Context {
     pub last_error: CString,
     pub working_dir: Option<CString>,
     pub binary_dir: Option<CString>,
-    pub archives: HopSlotMap<ArchiveKey, Archive>,
-    pub stringlists: HopSlotMap<StringListKey, StringList>,
-    pub watchers: HopSlotMap<WatcherKey, FileWatcher>,
+    pub archives: HandleMap<ArchiveKey, Archive>,
+    pub archive_writers: HandleMap<ArchiveWriterKey, ArchiveWriter>,
+    pub entrylists: HandleMap<EntryListKey, Vec<ArchiveEntry>>,
+    pub stringlists: HandleMap<StringListKey, StringList>,
+    pub strings: HandleMap<StringKey, CString>,
+    pub dirlists: HandleMap<DirListKey, DirEntryList>,
+    pub watchers: HandleMap<WatcherKey, FileWatcher>,
+    pub watch_signal: Arc<WakeSignal>,
+    pub eventlists: HandleMap<EventListKey, EventList>,
+    pub readers: HandleMap<ReaderKey, EntryReader>,
+    pub jobs: HandleMap<JobKey, CopyJob>,
+    pub reload_trackers: HandleMap<ReloadKey, ReloadTracker>,
+    pub vfs: Vfs,
 }
 
 fn format_entry(
@@ -99,9 +259,19 @@ impl Context {
             last_error: CString::new("").unwrap(),
             working_dir: None,
             binary_dir: None,
-            archives: HopSlotMap::with_key(),
-            stringlists: HopSlotMap::with_key(),
-            watchers: HopSlotMap::with_key(),
+            archives: HandleMap::with_key(),
+            archive_writers: HandleMap::with_key(),
+            entrylists: HandleMap::with_key(),
+            stringlists: HandleMap::with_key(),
+            strings: HandleMap::with_key(),
+            dirlists: HandleMap::with_key(),
+            watchers: HandleMap::with_key(),
+            watch_signal: Arc::new(WakeSignal::default()),
+            eventlists: HandleMap::with_key(),
+            readers: HandleMap::with_key(),
+            jobs: HandleMap::with_key(),
+            reload_trackers: HandleMap::with_key(),
+            vfs: Vfs::new(),
         }
     }
 
@@ -126,14 +296,36 @@ impl Context {
         };
     }
 
-    pub fn watch_path_err(&mut self, path: String, recursive: bool) -> Result<WatcherKey, String> {
-        let mut watcher = FileWatcher::new()?;
-        watcher.watch(path, recursive)?;
+    pub fn watch_path_err(
+        &mut self,
+        path: String,
+        recursive: bool,
+        options: &WatcherOptions,
+    ) -> Result<WatcherKey, String> {
+        let mut watcher = FileWatcher::new(options, self.watch_signal.clone())?;
+        if let Err(e) = watcher.watch(path.clone(), recursive) {
+            // e.g. the inotify watch limit is exhausted: retry by polling
+            if options.backend != BACKEND_AUTO || watcher.kind() == WATCHER_KIND_POLL {
+                return Err(e);
+            }
+            warn!("Native watch failed ({}), falling back to polling", e);
+            let options = WatcherOptions {
+                backend: BACKEND_POLL,
+                ..*options
+            };
+            watcher = FileWatcher::new(&options, self.watch_signal.clone())?;
+            watcher.watch(path, recursive)?;
+        }
         Ok(self.watchers.insert(watcher))
     }
 
-    pub fn watch_path(&mut self, path: String, recursive: bool) -> Option<WatcherKey> {
-        match self.watch_path_err(path, recursive) {
+    pub fn watch_path(
+        &mut self,
+        path: String,
+        recursive: bool,
+        options: &WatcherOptions,
+    ) -> Option<WatcherKey> {
+        match self.watch_path_err(path, recursive, options) {
             Ok(watcher) => Some(watcher),
             Err(s) => {
                 self.last_error = CString::new(s).unwrap();
@@ -155,6 +347,153 @@ impl Context {
         .watch(path, recursive)
     }
 
+    pub fn watch_remove_err(&mut self, watcher: WatcherKey, path: String) -> Result<(), String> {
+        match self.watchers.get_mut(watcher) {
+            Some(watcher) => watcher,
+            None => return Err(String::from("No such watcher")),
+        }
+        .unwatch(path)
+    }
+
+    pub fn watch_remove(&mut self, watcher: WatcherKey, path: String) -> bool {
+        match self.watch_remove_err(watcher, path) {
+            Ok(_) => true,
+            Err(s) => {
+                self.last_error = CString::new(s).unwrap();
+                false
+            }
+        }
+    }
+
+    pub fn watched_paths(&mut self, watcher: WatcherKey) -> Option<StringListKey> {
+        let paths = match self.watchers.get_mut(watcher) {
+            Some(watcher) => Ok(watcher.watched_paths()),
+            None => Err(String::from("No such watcher")),
+        };
+        self.paths_to_list(paths)
+    }
+
+    pub fn watcher_set_callback_err(
+        &mut self,
+        watcher: WatcherKey,
+        callback: Option<WatchCallback>,
+        userdata: *mut c_void,
+    ) -> Result<(), String> {
+        match self.watchers.get_mut(watcher) {
+            Some(watcher) => watcher,
+            None => return Err(String::from("No such watcher")),
+        }
+        .set_callback(callback, userdata)
+    }
+
+    pub fn watcher_set_callback(
+        &mut self,
+        watcher: WatcherKey,
+        callback: Option<WatchCallback>,
+        userdata: *mut c_void,
+    ) -> bool {
+        match self.watcher_set_callback_err(watcher, callback, userdata) {
+            Ok(_) => true,
+            Err(s) => {
+                self.last_error = CString::new(s).unwrap();
+                false
+            }
+        }
+    }
+
+    fn string_list_patterns(&self, list: Option<StringListKey>) -> Result<Vec<String>, String> {
+        let list = match list {
+            Some(list) => list,
+            None => return Ok(Vec::new()),
+        };
+        match self.stringlists.get(list) {
+            Some(list) => Ok(list
+                .iter()
+                .map(|s| s.to_string_lossy().into_owned())
+                .collect()),
+            None => Err(String::from("No such list")),
+        }
+    }
+
+    pub fn watcher_set_filter_err(
+        &mut self,
+        watcher: WatcherKey,
+        include: Option<StringListKey>,
+        exclude: Option<StringListKey>,
+    ) -> Result<(), String> {
+        let filter = PathFilter::new(
+            &self.string_list_patterns(include)?,
+            &self.string_list_patterns(exclude)?,
+        )?;
+        match self.watchers.get_mut(watcher) {
+            Some(watcher) => watcher.set_filter(filter),
+            None => return Err(String::from("No such watcher")),
+        };
+        Ok(())
+    }
+
+    pub fn watcher_set_filter(
+        &mut self,
+        watcher: WatcherKey,
+        include: Option<StringListKey>,
+        exclude: Option<StringListKey>,
+    ) -> bool {
+        match self.watcher_set_filter_err(watcher, include, exclude) {
+            Ok(_) => true,
+            Err(s) => {
+                self.last_error = CString::new(s).unwrap();
+                false
+            }
+        }
+    }
+
+    /// Block until one of `watchers` has events ready to poll, returning its
+    /// index, or None if `timeout` (None = forever) elapses first.
+    pub fn watcher_wait_err(
+        &mut self,
+        watchers: &[WatcherKey],
+        timeout: Option<Duration>,
+    ) -> Result<Option<usize>, String> {
+        if watchers.is_empty() {
+            return Err(String::from("No watchers to wait on"));
+        }
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        loop {
+            // read the generation first so an event arriving mid-check
+            // still wakes the wait below
+            let generation = self.watch_signal.generation();
+            let now = Instant::now();
+            let mut wake = deadline;
+            for (index, key) in watchers.iter().enumerate() {
+                let watcher = match self.watchers.get_mut(*key) {
+                    Some(watcher) => watcher,
+                    None => return Err(String::from("No such watcher")),
+                };
+                if watcher.collect(now) {
+                    return Ok(Some(index));
+                }
+                wake = wake.into_iter().chain(watcher.next_wake()).min();
+            }
+            if deadline.is_some_and(|deadline| now >= deadline) {
+                return Ok(None);
+            }
+            self.watch_signal.wait(generation, wake);
+        }
+    }
+
+    /// `watcher_wait_err` as a C return value: the ready watcher's index,
+    /// WAIT_TIMEOUT or WAIT_ERROR.
+    pub fn watcher_wait(&mut self, watchers: &[WatcherKey], timeout: Option<Duration>) -> i64 {
+        match self.watcher_wait_err(watchers, timeout) {
+            Ok(Some(index)) => index as i64,
+            Ok(None) => WAIT_TIMEOUT,
+            Err(s) => {
+                self.last_error = CString::new(s).unwrap();
+                WAIT_ERROR
+            }
+        }
+    }
+
     pub fn watcher_poll(&mut self, watcher: WatcherKey) -> Option<StringListKey> {
         let watcher = self.watchers.get_mut(watcher)?;
         let events = watcher.poll_events();
@@ -165,6 +504,26 @@ impl Context {
         }
     }
 
+    pub fn watcher_poll_errors(&mut self, watcher: WatcherKey) -> Option<StringListKey> {
+        let watcher = self.watchers.get_mut(watcher)?;
+        let errors = watcher.poll_errors();
+        if errors.is_empty() {
+            None
+        } else {
+            Some(self.stringlists.insert(errors))
+        }
+    }
+
+    pub fn watcher_poll_ex(&mut self, watcher: WatcherKey) -> Option<EventListKey> {
+        let watcher = self.watchers.get_mut(watcher)?;
+        let events = EventList::new(watcher.poll_typed_events());
+        if events.is_empty() {
+            None
+        } else {
+            Some(self.eventlists.insert(events))
+        }
+    }
+
     pub fn mount_archive_err(&mut self, path: String) -> Result<ArchiveKey, String> {
         let archive = Archive::open(path)?;
         Ok(self.archives.insert(archive))
@@ -180,11 +539,190 @@ impl Context {
         }
     }
 
+    pub fn mount_archive_nested_err(
+        &mut self,
+        parent: ArchiveKey,
+        filename: String,
+    ) -> Result<ArchiveKey, String> {
+        let archive = match self.archives.get_mut(parent) {
+            Some(parent) => parent.open_nested(filename)?,
+            None => return Err(String::from("No such archive")),
+        };
+        Ok(self.archives.insert(archive))
+    }
+
+    pub fn mount_archive_nested(
+        &mut self,
+        parent: ArchiveKey,
+        filename: String,
+    ) -> Option<ArchiveKey> {
+        match self.mount_archive_nested_err(parent, filename) {
+            Ok(archive) => Some(archive),
+            Err(s) => {
+                self.last_error = CString::new(s).unwrap();
+                None
+            }
+        }
+    }
+
+    pub fn insert_archive(&mut self, archive: Result<Archive, String>) -> Option<ArchiveKey> {
+        match archive {
+            Ok(archive) => Some(self.archives.insert(archive)),
+            Err(s) => {
+                self.last_error = CString::new(s).unwrap();
+                None
+            }
+        }
+    }
+
+    pub fn archive_set_password(&mut self, archive: ArchiveKey, password: Option<Vec<u8>>) -> bool {
+        match self.archives.get_mut(archive) {
+            Some(archive) => {
+                archive.set_password(password);
+                true
+            }
+            None => {
+                self.last_error = CString::new("No such archive").unwrap();
+                false
+            }
+        }
+    }
+
+    pub fn archive_set_auto_reload(&mut self, archive: ArchiveKey, enabled: bool) -> bool {
+        match self.archives.get_mut(archive) {
+            Some(archive) => {
+                archive.set_auto_reload(enabled);
+                true
+            }
+            None => {
+                self.last_error = CString::new("No such archive").unwrap();
+                false
+            }
+        }
+    }
+
     pub fn list_archive(&mut self, archive: ArchiveKey) -> Option<StringListKey> {
         let archive = self.archives.get_mut(archive)?;
         Some(self.stringlists.insert(archive.list_files()))
     }
 
+    pub fn list_archive_ex(&mut self, archive: ArchiveKey) -> Option<EntryListKey> {
+        let archive = self.archives.get_mut(archive)?;
+        Some(self.entrylists.insert(archive.list_entries()))
+    }
+
+    pub fn archive_list_dir(
+        &mut self,
+        archive: ArchiveKey,
+        dir: String,
+        files_only: bool,
+    ) -> Option<StringListKey> {
+        let names = match self.archives.get_mut(archive) {
+            Some(archive) => archive.list_dir(&dir, files_only),
+            None => Err(String::from("No such archive")),
+        };
+        self.paths_to_list(names)
+    }
+
+    pub fn create_archive_err(&mut self, path: String) -> Result<ArchiveWriterKey, String> {
+        let writer = ArchiveWriter::create(path)?;
+        Ok(self.archive_writers.insert(writer))
+    }
+
+    pub fn create_archive(&mut self, path: String) -> Option<ArchiveWriterKey> {
+        match self.create_archive_err(path) {
+            Ok(writer) => Some(writer),
+            Err(s) => {
+                self.last_error = CString::new(s).unwrap();
+                None
+            }
+        }
+    }
+
+    pub fn archive_add_file(
+        &mut self,
+        writer: ArchiveWriterKey,
+        name: String,
+        data: &[u8],
+        compression_level: i32,
+    ) -> bool {
+        let result = match self.archive_writers.get_mut(writer) {
+            Some(writer) => writer.add_file(name, data, compression_level),
+            None => Err(String::from("No such archive writer")),
+        };
+        match result {
+            Ok(_) => true,
+            Err(s) => {
+                self.last_error = CString::new(s).unwrap();
+                false
+            }
+        }
+    }
+
+    pub fn archive_add_dir(&mut self, writer: ArchiveWriterKey, name: String) -> bool {
+        let result = match self.archive_writers.get_mut(writer) {
+            Some(writer) => writer.add_dir(name),
+            None => Err(String::from("No such archive writer")),
+        };
+        match result {
+            Ok(_) => true,
+            Err(s) => {
+                self.last_error = CString::new(s).unwrap();
+                false
+            }
+        }
+    }
+
+    pub fn archive_finish(&mut self, writer: ArchiveWriterKey) -> bool {
+        let result = match self.archive_writers.remove(writer) {
+            Some(writer) => writer.finish(),
+            None => Err(String::from("No such archive writer")),
+        };
+        match result {
+            Ok(_) => true,
+            Err(s) => {
+                self.last_error = CString::new(s).unwrap();
+                false
+            }
+        }
+    }
+
+    pub fn open_archive_entry_err(
+        &mut self,
+        archive: ArchiveKey,
+        name: String,
+    ) -> Result<ReaderKey, String> {
+        let reader = match self.archives.get_mut(archive) {
+            Some(archive) => archive.open_entry(name)?,
+            None => return Err(String::from("No such archive")),
+        };
+        Ok(self.readers.insert(reader))
+    }
+
+    pub fn open_archive_entry(&mut self, archive: ArchiveKey, name: String) -> Option<ReaderKey> {
+        match self.open_archive_entry_err(archive, name) {
+            Ok(reader) => Some(reader),
+            Err(s) => {
+                self.last_error = CString::new(s).unwrap();
+                None
+            }
+        }
+    }
+
+    pub fn reader_read(&mut self, reader: ReaderKey, dest: &mut [u8]) -> Option<usize> {
+        let result = match self.readers.get_mut(reader) {
+            Some(reader) => reader.read(dest),
+            None => Err(String::from("No such reader")),
+        };
+        match result {
+            Ok(n) => Some(n),
+            Err(s) => {
+                self.last_error = CString::new(s).unwrap();
+                None
+            }
+        }
+    }
+
     pub fn listdir_err(
         &mut self,
         path: String,
@@ -219,6 +757,307 @@ impl Context {
         }
     }
 
+    pub fn vfs_mount_err(
+        &mut self,
+        prefix: String,
+        source: MountSource,
+        priority: i64,
+    ) -> Result<MountKey, String> {
+        match &source {
+            MountSource::Dir(dir) if !dir.is_dir() => {
+                return Err(format!("Not a directory: {}", dir.to_string_lossy()))
+            }
+            MountSource::Archive(archive) if !self.archives.contains_key(*archive) => {
+                return Err(String::from("No such archive"))
+            }
+            _ => (),
+        }
+        self.vfs.mount(&prefix, source, priority)
+    }
+
+    pub fn vfs_mount(
+        &mut self,
+        prefix: String,
+        source: MountSource,
+        priority: i64,
+    ) -> Option<MountKey> {
+        match self.vfs_mount_err(prefix, source, priority) {
+            Ok(mount) => Some(mount),
+            Err(s) => {
+                self.last_error = CString::new(s).unwrap();
+                None
+            }
+        }
+    }
+
+    /// Pick up rebuilt zips (for archives with auto-reload on) before the
+    /// vfs looks inside them.
+    pub fn refresh_archives(&mut self) {
+        for archive in self.archives.values_mut() {
+            archive.refresh();
+        }
+    }
+
+    pub fn vfs_read(&mut self, path: String) -> Option<Vec<u8>> {
+        self.refresh_archives();
+        match self.vfs.read(&path, &mut self.archives) {
+            Ok(data) => Some(data),
+            Err(s) => {
+                self.last_error = CString::new(s).unwrap();
+                None
+            }
+        }
+    }
+
+    pub fn vfs_filesize(&mut self, path: String) -> Option<u64> {
+        self.refresh_archives();
+        match self.vfs.filesize(&path, &mut self.archives) {
+            Ok(size) => Some(size),
+            Err(s) => {
+                self.last_error = CString::new(s).unwrap();
+                None
+            }
+        }
+    }
+
+    pub fn vfs_list_err(
+        &mut self,
+        path: String,
+        files_only: bool,
+    ) -> Result<StringListKey, String> {
+        self.refresh_archives();
+        let mut items: Vec<CString> = Vec::new();
+        for name in self.vfs.list(&path, files_only, &self.archives)? {
+            items.push(CString::new(name).map_err(|e| e.to_string())?);
+        }
+        Ok(self.stringlists.insert(items))
+    }
+
+    pub fn vfs_list(&mut self, path: String, files_only: bool) -> Option<StringListKey> {
+        match self.vfs_list_err(path, files_only) {
+            Ok(strlist) => Some(strlist),
+            Err(s) => {
+                self.last_error = CString::new(s).unwrap();
+                None
+            }
+        }
+    }
+
+    pub fn listdir_ex(&mut self, path: String, files_only: bool) -> Option<DirListKey> {
+        match DirEntryList::read_dir(path, files_only) {
+            Ok(dirlist) => Some(self.dirlists.insert(dirlist)),
+            Err(s) => {
+                self.last_error = CString::new(s).unwrap();
+                None
+            }
+        }
+    }
+
+    pub fn walkdir_err(
+        &mut self,
+        path: String,
+        options: WalkOptions,
+    ) -> Result<StringListKey, String> {
+        let mut items: Vec<CString> = Vec::new();
+        for relpath in walk_dir(&path, &options)? {
+            items.push(CString::new(relpath).map_err(|e| e.to_string())?);
+        }
+        Ok(self.stringlists.insert(items))
+    }
+
+    pub fn walkdir(&mut self, path: String, options: WalkOptions) -> Option<StringListKey> {
+        match self.walkdir_err(path, options) {
+            Ok(strlist) => Some(strlist),
+            Err(s) => {
+                self.last_error = CString::new(s).unwrap();
+                None
+            }
+        }
+    }
+
+    pub fn file_size(&mut self, path: String) -> Option<u64> {
+        match fileio::file_size(&path) {
+            Ok(size) => Some(size),
+            Err(s) => {
+                self.last_error = CString::new(s).unwrap();
+                None
+            }
+        }
+    }
+
+    pub fn read_file(&mut self, path: String, dest: &mut [u8]) -> Option<usize> {
+        match fileio::read_file_into(&path, dest) {
+            Ok(n) => Some(n),
+            Err(s) => {
+                self.last_error = CString::new(s).unwrap();
+                None
+            }
+        }
+    }
+
+    pub fn write_file(&mut self, path: String, data: &[u8], append: bool) -> bool {
+        match fileio::write_file(&path, data, append) {
+            Ok(_) => true,
+            Err(s) => {
+                self.last_error = CString::new(s).unwrap();
+                false
+            }
+        }
+    }
+
+    pub fn write_file_atomic(&mut self, path: String, data: &[u8]) -> bool {
+        match fileio::write_file_atomic(&path, data) {
+            Ok(_) => true,
+            Err(s) => {
+                self.last_error = CString::new(s).unwrap();
+                false
+            }
+        }
+    }
+
+    pub fn paths_to_list(&mut self, paths: Result<Vec<String>, String>) -> Option<StringListKey> {
+        let paths = paths.and_then(|paths| {
+            paths
+                .into_iter()
+                .map(|path| CString::new(path).map_err(|e| e.to_string()))
+                .collect::<Result<StringList, String>>()
+        });
+        match paths {
+            Ok(strlist) => Some(self.stringlists.insert(strlist)),
+            Err(s) => {
+                self.last_error = CString::new(s).unwrap();
+                None
+            }
+        }
+    }
+
+    pub fn copy_tree_archive_source(
+        &mut self,
+        archive: ArchiveKey,
+        prefix: String,
+    ) -> Result<CopySource, String> {
+        match self.archives.get_mut(archive) {
+            Some(archive) => {
+                archive.refresh();
+                Ok(CopySource::Archive(
+                    archive.reopen(),
+                    prefix,
+                    archive.password().map(<[u8]>::to_vec),
+                ))
+            }
+            None => Err(String::from("No such archive")),
+        }
+    }
+
+    pub fn copy_tree(
+        &mut self,
+        source: Result<CopySource, String>,
+        dst: String,
+        policy: u32,
+    ) -> Option<JobKey> {
+        match source.and_then(|source| CopyJob::start(source, dst, policy)) {
+            Ok(job) => Some(self.jobs.insert(job)),
+            Err(s) => {
+                self.last_error = CString::new(s).unwrap();
+                None
+            }
+        }
+    }
+
+    pub fn job_progress(&mut self, job: JobKey) -> Option<JobProgress> {
+        let job = match self.jobs.get(job) {
+            Some(job) => job,
+            None => {
+                self.last_error = CString::new("No such job").unwrap();
+                return None;
+            }
+        };
+        if let Some(s) = job.error() {
+            self.last_error = CString::new(s).unwrap();
+        }
+        Some(job.progress())
+    }
+
+    pub fn reload_create(&mut self, debounce_ms: u32) -> Option<ReloadKey> {
+        match ReloadTracker::new(debounce_ms, self.watch_signal.clone()) {
+            Ok(tracker) => Some(self.reload_trackers.insert(tracker)),
+            Err(s) => {
+                self.last_error = CString::new(s).unwrap();
+                None
+            }
+        }
+    }
+
+    /// Track `name` as loaded from `source`, which is a path on disk or (if
+    /// None) the file `name` is currently served from through the vfs.
+    pub fn reload_track_err(
+        &mut self,
+        tracker: ReloadKey,
+        name: String,
+        source: Option<String>,
+    ) -> Result<(), String> {
+        let source = match source {
+            Some(source) => PathBuf::from(source),
+            None => {
+                self.refresh_archives();
+                self.vfs.source_path(&name, &self.archives)?
+            }
+        };
+        match self.reload_trackers.get_mut(tracker) {
+            Some(tracker) => tracker.track(name, &source),
+            None => Err(String::from("No such tracker")),
+        }
+    }
+
+    pub fn reload_track_archive_err(
+        &mut self,
+        tracker: ReloadKey,
+        name: String,
+        archive: ArchiveKey,
+    ) -> Result<(), String> {
+        let source = match self.archives.get(archive) {
+            Some(archive) => archive.source_file()?,
+            None => return Err(String::from("No such archive")),
+        };
+        match self.reload_trackers.get_mut(tracker) {
+            Some(tracker) => tracker.track(name, &source),
+            None => Err(String::from("No such tracker")),
+        }
+    }
+
+    pub fn reload_track(&mut self, tracked: Result<(), String>) -> bool {
+        match tracked {
+            Ok(_) => true,
+            Err(s) => {
+                self.last_error = CString::new(s).unwrap();
+                false
+            }
+        }
+    }
+
+    pub fn reload_poll(&mut self, tracker: ReloadKey) -> Option<StringListKey> {
+        let tracker = self.reload_trackers.get_mut(tracker)?;
+        let stale = tracker.poll();
+        if let Some(s) = tracker.error() {
+            self.last_error = CString::new(s).unwrap();
+        }
+        if stale.is_empty() {
+            None
+        } else {
+            self.paths_to_list(Ok(stale))
+        }
+    }
+
+    pub fn string_handle(&mut self, s: Result<String, String>) -> Option<StringKey> {
+        match s.and_then(|s| CString::new(s).map_err(|e| e.to_string())) {
+            Ok(s) => Some(self.strings.insert(s)),
+            Err(s) => {
+                self.last_error = CString::new(s).unwrap();
+                None
+            }
+        }
+    }
+
     pub fn splitpath(&mut self, path: String) -> Option<StringListKey> {
         let path = Path::new(&path);
         let mut parts: Vec<CString> = Vec::new();
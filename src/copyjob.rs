@@ -0,0 +1,304 @@
+use crate::archive::{zip_by_index, zip_local_time, ZipReader};
+use crate::fileio::sibling_temp_path;
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::SystemTime;
+
+pub const POLICY_SKIP: u32 = 0;
+pub const POLICY_OVERWRITE: u32 = 1;
+pub const POLICY_OVERWRITE_IF_NEWER: u32 = 2;
+
+pub const STATUS_FAILED: i32 = -1;
+pub const STATUS_RUNNING: i32 = 0;
+pub const STATUS_FINISHED: i32 = 1;
+
+const CHUNK_SIZE: usize = 64 * 1024;
+
+pub enum CopySource {
+    Dir(PathBuf),
+    // zip, prefix to copy, password for encrypted entries
+    Archive(ZipReader, String, Option<Vec<u8>>),
+}
+
+/// Mirrors `trussfs_job_progress` in trussfs.h.
+#[repr(C)]
+#[derive(Default)]
+pub struct JobProgress {
+    pub status: i32,
+    pub files_total: u64,
+    pub files_copied: u64,
+    pub files_skipped: u64,
+    pub bytes_total: u64,
+    pub bytes_copied: u64,
+    pub bytes_skipped: u64,
+}
+
+#[derive(Default)]
+struct SharedProgress {
+    files_total: AtomicU64,
+    files_copied: AtomicU64,
+    files_skipped: AtomicU64,
+    bytes_total: AtomicU64,
+    bytes_copied: AtomicU64,
+    bytes_skipped: AtomicU64,
+    finished: AtomicBool,
+    cancelled: AtomicBool,
+    error: Mutex<Option<String>>,
+}
+
+pub struct CopyJob {
+    progress: Arc<SharedProgress>,
+    thread: Option<JoinHandle<()>>,
+}
+
+enum ItemSource {
+    File(PathBuf),
+    ArchiveIndex(usize),
+}
+
+struct CopyItem {
+    rel: PathBuf,
+    is_dir: bool,
+    size: u64,
+    mtime: Option<SystemTime>,
+    source: ItemSource,
+}
+
+fn plan_dir(dir: &Path, rel: &Path, items: &mut Vec<CopyItem>) -> Result<(), String> {
+    let mut entries: Vec<fs::DirEntry> = fs::read_dir(dir)
+        .map_err(|e| format!("{}: {}", dir.to_string_lossy(), e))?
+        .flatten()
+        .collect();
+    entries.sort_by_key(|entry| entry.file_name());
+    for entry in entries {
+        let path = entry.path();
+        let child_rel = rel.join(entry.file_name());
+        // symlinked files are copied by content, symlinked directories are
+        // skipped so that link cycles can't recurse forever
+        let is_real_dir = match fs::symlink_metadata(&path) {
+            Ok(metadata) => metadata.is_dir(),
+            Err(_) => continue,
+        };
+        let metadata = match fs::metadata(&path) {
+            Ok(metadata) => metadata,
+            Err(_) => continue,
+        };
+        if is_real_dir {
+            items.push(CopyItem {
+                rel: child_rel.clone(),
+                is_dir: true,
+                size: 0,
+                mtime: None,
+                source: ItemSource::File(path.clone()),
+            });
+            plan_dir(&path, &child_rel, items)?;
+        } else if metadata.is_file() {
+            items.push(CopyItem {
+                rel: child_rel,
+                is_dir: false,
+                size: metadata.len(),
+                mtime: metadata.modified().ok(),
+                source: ItemSource::File(path),
+            });
+        }
+    }
+    Ok(())
+}
+
+fn plan_archive(zip: &mut ZipReader, prefix: &str, items: &mut Vec<CopyItem>) {
+    let prefix = prefix.trim_matches('/');
+    for index in 0..zip.len() {
+        let file = match zip.by_index_raw(index) {
+            Ok(file) => file,
+            Err(_) => continue,
+        };
+        // entries with unsafe paths (absolute, "..") are never extracted
+        let name = match file.enclosed_name() {
+            Some(name) => name.to_path_buf(),
+            None => continue,
+        };
+        let rel = match name.strip_prefix(prefix) {
+            Ok(rel) if !rel.as_os_str().is_empty() => rel.to_path_buf(),
+            _ => continue,
+        };
+        items.push(CopyItem {
+            rel,
+            is_dir: file.is_dir(),
+            size: file.size(),
+            mtime: zip_local_time(file.last_modified()),
+            source: ItemSource::ArchiveIndex(index),
+        });
+    }
+}
+
+fn should_copy(item: &CopyItem, dst: &Path, policy: u32) -> bool {
+    let dst_mtime = match fs::metadata(dst) {
+        Ok(metadata) => metadata.modified().ok(),
+        Err(_) => return true,
+    };
+    match policy {
+        POLICY_SKIP => false,
+        POLICY_OVERWRITE => true,
+        _ => match (item.mtime, dst_mtime) {
+            (Some(src), Some(dst)) => src > dst,
+            _ => true,
+        },
+    }
+}
+
+impl SharedProgress {
+    // Copy through a sibling temp file renamed into place at the end, so a
+    // cancelled or failed copy never leaves a truncated file at `dst` (which
+    // would look newer than its source to the next OVERWRITE_IF_NEWER run).
+    fn copy_stream(&self, src: &mut dyn Read, dst: &Path) -> Result<(), String> {
+        let temppath = sibling_temp_path(dst)?;
+        let result = self.write_stream(src, &temppath).and_then(|_| {
+            fs::rename(&temppath, dst).map_err(|e| format!("{}: {}", dst.to_string_lossy(), e))
+        });
+        if result.is_err() {
+            let _ = fs::remove_file(&temppath);
+        }
+        result
+    }
+
+    fn write_stream(&self, src: &mut dyn Read, path: &Path) -> Result<(), String> {
+        let mut out = OpenOptions::new()
+            .create_new(true)
+            .write(true)
+            .open(path)
+            .map_err(|e| format!("{}: {}", path.to_string_lossy(), e))?;
+        let mut buffer = vec![0u8; CHUNK_SIZE];
+        loop {
+            if self.cancelled.load(Ordering::Relaxed) {
+                return Err(String::from("Cancelled"));
+            }
+            let n = match src.read(&mut buffer) {
+                Ok(0) => return Ok(()),
+                Ok(n) => n,
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e.to_string()),
+            };
+            out.write_all(&buffer[..n]).map_err(|e| e.to_string())?;
+            self.bytes_copied.fetch_add(n as u64, Ordering::Relaxed);
+        }
+    }
+
+    fn run(&self, source: CopySource, dst: PathBuf, policy: u32) -> Result<(), String> {
+        let mut items: Vec<CopyItem> = Vec::new();
+        let (mut zip, password) = match source {
+            CopySource::Dir(dir) => {
+                plan_dir(&dir, Path::new(""), &mut items)?;
+                (None, None)
+            }
+            CopySource::Archive(mut zip, prefix, password) => {
+                plan_archive(&mut zip, &prefix, &mut items);
+                (Some(zip), password)
+            }
+        };
+        let files = items.iter().filter(|item| !item.is_dir);
+        self.files_total
+            .store(files.clone().count() as u64, Ordering::Relaxed);
+        self.bytes_total
+            .store(files.map(|item| item.size).sum(), Ordering::Relaxed);
+
+        fs::create_dir_all(&dst).map_err(|e| e.to_string())?;
+        for item in items {
+            let target = dst.join(&item.rel);
+            if item.is_dir {
+                fs::create_dir_all(&target).map_err(|e| e.to_string())?;
+                continue;
+            }
+            if !should_copy(&item, &target, policy) {
+                self.files_skipped.fetch_add(1, Ordering::Relaxed);
+                self.bytes_skipped.fetch_add(item.size, Ordering::Relaxed);
+                continue;
+            }
+            if let Some(parent) = target.parent() {
+                fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+            }
+            match (&item.source, zip.as_mut()) {
+                (ItemSource::File(path), _) => {
+                    let mut file = File::open(path).map_err(|e| e.to_string())?;
+                    self.copy_stream(&mut file, &target)?;
+                }
+                (ItemSource::ArchiveIndex(index), Some(zip)) => {
+                    let mut file = zip_by_index(zip, *index, password.as_deref())?;
+                    self.copy_stream(&mut file, &target)?;
+                }
+                (ItemSource::ArchiveIndex(_), None) => {
+                    return Err(String::from("Archive entry planned without an archive"))
+                }
+            }
+            self.files_copied.fetch_add(1, Ordering::Relaxed);
+        }
+        Ok(())
+    }
+}
+
+impl CopyJob {
+    /// Start copying `source` into the directory `dst` on a background thread.
+    pub fn start(source: CopySource, dst: String, policy: u32) -> Result<Self, String> {
+        if policy > POLICY_OVERWRITE_IF_NEWER {
+            return Err(format!("Invalid copy policy: {}", policy));
+        }
+        if let CopySource::Dir(dir) = &source {
+            if !dir.is_dir() {
+                return Err(format!("Not a directory: {}", dir.to_string_lossy()));
+            }
+        }
+        let progress = Arc::new(SharedProgress::default());
+        let shared = progress.clone();
+        let thread = std::thread::Builder::new()
+            .name(String::from("trussfs-copy"))
+            .spawn(move || {
+                if let Err(e) = shared.run(source, PathBuf::from(dst), policy) {
+                    *shared.error.lock().unwrap() = Some(e);
+                }
+                shared.finished.store(true, Ordering::Release);
+            })
+            .map_err(|e| e.to_string())?;
+        Ok(CopyJob {
+            progress,
+            thread: Some(thread),
+        })
+    }
+
+    pub fn progress(&self) -> JobProgress {
+        let shared = &self.progress;
+        let status = if !shared.finished.load(Ordering::Acquire) {
+            STATUS_RUNNING
+        } else if shared.error.lock().unwrap().is_some() {
+            STATUS_FAILED
+        } else {
+            STATUS_FINISHED
+        };
+        JobProgress {
+            status,
+            files_total: shared.files_total.load(Ordering::Relaxed),
+            files_copied: shared.files_copied.load(Ordering::Relaxed),
+            files_skipped: shared.files_skipped.load(Ordering::Relaxed),
+            bytes_total: shared.bytes_total.load(Ordering::Relaxed),
+            bytes_copied: shared.bytes_copied.load(Ordering::Relaxed),
+            bytes_skipped: shared.bytes_skipped.load(Ordering::Relaxed),
+        }
+    }
+
+    pub fn error(&self) -> Option<String> {
+        self.progress.error.lock().unwrap().clone()
+    }
+}
+
+impl Drop for CopyJob {
+    fn drop(&mut self) {
+        // freeing a running job cancels it; the copy loop checks the flag
+        // between chunks so this join is short
+        self.progress.cancelled.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
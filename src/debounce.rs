@@ -0,0 +1,95 @@
+use notify::event::{AccessKind, AccessMode, CreateKind, ModifyKind, RemoveKind};
+use notify::{Event, EventKind};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+#[derive(Clone, Copy)]
+struct PendingPath {
+    // whether the burst started by creating the path
+    created: bool,
+    last: Instant,
+}
+
+/// Coalesces bursts of events into a single event per path, emitted once
+/// the path has been quiet for `window`. The emitted event reflects the
+/// final state of the path: a create if the burst began by creating it, a
+/// remove if it no longer exists, and a modify otherwise. Paths that are
+/// created and removed within one burst produce nothing. Accesses don't
+/// change a path's state and are dropped, except for closing a file that
+/// was open for writing, which counts as a modification.
+pub struct Debouncer {
+    window: Duration,
+    pending: HashMap<PathBuf, PendingPath>,
+}
+
+impl Debouncer {
+    pub fn new(window: Duration) -> Self {
+        Debouncer {
+            window,
+            pending: HashMap::new(),
+        }
+    }
+
+    pub fn push(&mut self, time: Instant, evt: Event) {
+        match evt.kind {
+            EventKind::Access(AccessKind::Close(AccessMode::Write)) => (),
+            EventKind::Access(_) => return,
+            _ => (),
+        }
+        // only an explicit create counts: the destination of a rename may
+        // have replaced an existing file, so that is a modification
+        let created = matches!(evt.kind, EventKind::Create(_));
+        for path in evt.paths {
+            self.pending
+                .entry(path)
+                .and_modify(|pending| pending.last = time)
+                .or_insert(PendingPath {
+                    created,
+                    last: time,
+                });
+        }
+    }
+
+    /// When the earliest pending path will have settled, if any.
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.pending
+            .values()
+            .map(|pending| pending.last + self.window)
+            .min()
+    }
+
+    /// Take the coalesced events for every path that has settled by `now`.
+    pub fn flush(&mut self, now: Instant) -> Vec<(Instant, Event)> {
+        let window = self.window;
+        let mut settled: Vec<(PathBuf, PendingPath)> = Vec::new();
+        self.pending.retain(|path, pending| {
+            if now.duration_since(pending.last) >= window {
+                settled.push((path.clone(), *pending));
+                false
+            } else {
+                true
+            }
+        });
+        settled.sort_by_key(|(_, pending)| pending.last);
+
+        let mut events: Vec<(Instant, Event)> = Vec::new();
+        for (path, pending) in settled {
+            let exists = path.symlink_metadata().is_ok();
+            let kind = match (exists, pending.created) {
+                (true, true) => EventKind::Create(CreateKind::Any),
+                (true, false) => EventKind::Modify(ModifyKind::Any),
+                (false, false) => EventKind::Remove(RemoveKind::Any),
+                (false, true) => continue,
+            };
+            events.push((pending.last, Event::new(kind).add_path(path)));
+        }
+        events
+    }
+}
+
+/// Whether an event is something the debouncer can coalesce; events that
+/// aren't about specific paths (e.g. rescan requests) pass straight through.
+pub fn is_debounceable(evt: &Event) -> bool {
+    !evt.paths.is_empty()
+}
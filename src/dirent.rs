@@ -0,0 +1,116 @@
+use std::ffi::CString;
+use std::fs;
+use std::os::raw::c_char;
+use std::path::Path;
+use std::ptr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub const KIND_OTHER: u32 = 0;
+pub const KIND_FILE: u32 = 1;
+pub const KIND_DIR: u32 = 2;
+
+/// Mirrors `trussfs_dirent` in trussfs.h. Times are seconds since the Unix
+/// epoch (0 if unavailable); `name` is owned by the list it came from.
+#[repr(C)]
+pub struct DirEnt {
+    pub name: *const c_char,
+    pub kind: u32,
+    pub is_symlink: bool,
+    pub size: u64,
+    pub mtime: f64,
+    pub btime: f64,
+    pub permissions: u32,
+}
+
+pub struct DirEntryList {
+    // backing storage for the `name` pointers in `entries`
+    names: Vec<CString>,
+    entries: Vec<DirEnt>,
+}
+
+fn time_to_secs(time: std::io::Result<SystemTime>) -> f64 {
+    match time.ok().map(|t| t.duration_since(UNIX_EPOCH)) {
+        Some(Ok(duration)) => duration.as_secs_f64(),
+        _ => 0.0,
+    }
+}
+
+#[cfg(unix)]
+fn permissions(metadata: &fs::Metadata) -> u32 {
+    use std::os::unix::fs::PermissionsExt;
+    metadata.permissions().mode()
+}
+
+#[cfg(not(unix))]
+fn permissions(metadata: &fs::Metadata) -> u32 {
+    if metadata.permissions().readonly() {
+        0o444
+    } else {
+        0o666
+    }
+}
+
+/// Stat a path into a `DirEnt` with a null name; symlinks are followed for
+/// everything except `is_symlink`.
+pub fn stat_path(path: &Path) -> Option<DirEnt> {
+    let is_symlink = fs::symlink_metadata(path).ok()?.is_symlink();
+    let metadata = fs::metadata(path).ok()?;
+    let kind = if metadata.is_file() {
+        KIND_FILE
+    } else if metadata.is_dir() {
+        KIND_DIR
+    } else {
+        KIND_OTHER
+    };
+    Some(DirEnt {
+        name: ptr::null(),
+        kind,
+        is_symlink,
+        size: metadata.len(),
+        mtime: time_to_secs(metadata.modified()),
+        // birth time only; not every filesystem records it, and unix
+        // st_ctime (status change) is a different thing, so no fallback
+        btime: time_to_secs(metadata.created()),
+        permissions: permissions(&metadata),
+    })
+}
+
+impl DirEntryList {
+    pub fn read_dir(path: String, files_only: bool) -> Result<Self, String> {
+        let mut names: Vec<CString> = Vec::new();
+        let mut entries: Vec<DirEnt> = Vec::new();
+        for entry in fs::read_dir(path).map_err(|e| e.to_string())? {
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(_) => continue,
+            };
+            let dirent = match stat_path(&entry.path()) {
+                Some(dirent) => dirent,
+                None => continue,
+            };
+            if files_only && dirent.kind != KIND_FILE {
+                continue;
+            }
+            let name = match CString::new(entry.file_name().to_string_lossy().into_owned()) {
+                Ok(name) => name,
+                Err(_) => continue,
+            };
+            names.push(name);
+            entries.push(dirent);
+        }
+        // the CString buffers are on the heap, so these stay valid as the
+        // list itself moves around
+        for (entry, name) in entries.iter_mut().zip(names.iter()) {
+            entry.name = name.as_ptr();
+        }
+        Ok(DirEntryList { names, entries })
+    }
+
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    pub fn get(&self, index: usize) -> Option<&DirEnt> {
+        self.entries.get(index)
+    }
+}
@@ -0,0 +1,198 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+static TEMP_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+pub fn file_size(path: &str) -> Result<u64, String> {
+    let metadata = fs::metadata(path).map_err(|e| e.to_string())?;
+    if !metadata.is_file() {
+        return Err(format!("Not a file: {}", path));
+    }
+    Ok(metadata.len())
+}
+
+/// Read an entire file directly into `dest`, failing (like `copy_data`)
+/// if the file does not fit.
+pub fn read_file_into(path: &str, dest: &mut [u8]) -> Result<usize, String> {
+    let mut file = File::open(path).map_err(|e| e.to_string())?;
+    let size = file.metadata().map_err(|e| e.to_string())?.len();
+    if size > dest.len() as u64 {
+        return Err(format!(
+            "Buffer too small: {} needs {} bytes, got {}",
+            path,
+            size,
+            dest.len()
+        ));
+    }
+    let mut total = 0;
+    while total < dest.len() {
+        match file.read(&mut dest[total..]) {
+            Ok(0) => return Ok(total),
+            Ok(n) => total += n,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e.to_string()),
+        }
+    }
+    // the buffer is full; make sure the file didn't grow past it meanwhile
+    let mut probe = [0u8; 1];
+    match file.read(&mut probe) {
+        Ok(0) => Ok(total),
+        Ok(_) => Err(format!("Buffer too small: {} grew while reading", path)),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+pub fn write_file(path: &str, data: &[u8], append: bool) -> Result<(), String> {
+    let mut file = OpenOptions::new()
+        .create(true)
+        .write(true)
+        .append(append)
+        .truncate(!append)
+        .open(path)
+        .map_err(|e| e.to_string())?;
+    file.write_all(data).map_err(|e| e.to_string())
+}
+
+// A temp path next to `path` (so the final rename stays on one filesystem)
+pub fn sibling_temp_path(path: &Path) -> Result<PathBuf, String> {
+    let filename = match path.file_name() {
+        Some(filename) => filename.to_string_lossy().into_owned(),
+        None => return Err(format!("Not a file path: {}", path.to_string_lossy())),
+    };
+    let tempname = format!(
+        ".{}.{}.{}.tmp",
+        filename,
+        std::process::id(),
+        TEMP_COUNTER.fetch_add(1, Ordering::Relaxed)
+    );
+    Ok(path.with_file_name(tempname))
+}
+
+fn write_synced(temppath: &Path, target: &Path, data: &[u8]) -> std::io::Result<()> {
+    let mut file = OpenOptions::new()
+        .create_new(true)
+        .write(true)
+        .open(temppath)?;
+    if let Ok(metadata) = fs::metadata(target) {
+        // keep the permissions of the file being replaced
+        file.set_permissions(metadata.permissions())?;
+    }
+    file.write_all(data)?;
+    file.sync_all()
+}
+
+/// Write `data` to a sibling temp file, fsync it, and rename it over `path`,
+/// so readers (and file watchers) only ever see the old or the new contents.
+pub fn write_file_atomic(path: &str, data: &[u8]) -> Result<(), String> {
+    let target = Path::new(path);
+    let temppath = sibling_temp_path(target)?;
+    let result = write_synced(&temppath, target, data).and_then(|_| fs::rename(&temppath, target));
+    if let Err(e) = result {
+        let _ = fs::remove_file(&temppath);
+        return Err(e.to_string());
+    }
+    #[cfg(unix)]
+    {
+        // persist the rename itself; failure here doesn't undo the write
+        if let Some(parent) = target.parent() {
+            let parent = if parent.as_os_str().is_empty() {
+                Path::new(".")
+            } else {
+                parent
+            };
+            if let Ok(dir) = File::open(parent) {
+                let _ = dir.sync_all();
+            }
+        }
+    }
+    Ok(())
+}
+
+fn path_string(path: &Path) -> String {
+    path.to_string_lossy().into_owned()
+}
+
+pub fn copy_file(src: &str, dst: &str, dry_run: bool) -> Result<Vec<String>, String> {
+    if !Path::new(src).is_file() {
+        return Err(format!("Not a file: {}", src));
+    }
+    if !dry_run {
+        fs::copy(src, dst).map_err(|e| e.to_string())?;
+    }
+    Ok(vec![dst.to_string()])
+}
+
+pub fn rename(src: &str, dst: &str, dry_run: bool) -> Result<Vec<String>, String> {
+    fs::symlink_metadata(src).map_err(|e| format!("{}: {}", src, e))?;
+    if !dry_run {
+        fs::rename(src, dst).map_err(|e| e.to_string())?;
+    }
+    Ok(vec![src.to_string(), dst.to_string()])
+}
+
+pub fn remove_file(path: &str, dry_run: bool) -> Result<Vec<String>, String> {
+    let metadata = fs::symlink_metadata(path).map_err(|e| format!("{}: {}", path, e))?;
+    if metadata.is_dir() {
+        return Err(format!("Is a directory: {}", path));
+    }
+    if !dry_run {
+        fs::remove_file(path).map_err(|e| e.to_string())?;
+    }
+    Ok(vec![path.to_string()])
+}
+
+pub fn remove_dir(path: &str, dry_run: bool) -> Result<Vec<String>, String> {
+    let metadata = fs::symlink_metadata(path).map_err(|e| format!("{}: {}", path, e))?;
+    if !metadata.is_dir() {
+        return Err(format!("Not a directory: {}", path));
+    }
+    if dry_run {
+        // mirror the "directory not empty" failure of the real removal
+        let mut entries = fs::read_dir(path).map_err(|e| e.to_string())?;
+        if entries.next().is_some() {
+            return Err(format!("Directory not empty: {}", path));
+        }
+    } else {
+        fs::remove_dir(path).map_err(|e| e.to_string())?;
+    }
+    Ok(vec![path.to_string()])
+}
+
+// Post-order listing of everything under (and including) `path`; symlinks
+// are listed but never followed.
+fn collect_tree(path: &Path, paths: &mut Vec<(PathBuf, bool)>) -> Result<(), String> {
+    let metadata =
+        fs::symlink_metadata(path).map_err(|e| format!("{}: {}", path_string(path), e))?;
+    if metadata.is_dir() {
+        let mut children: Vec<PathBuf> = fs::read_dir(path)
+            .map_err(|e| e.to_string())?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .collect();
+        children.sort();
+        for child in children {
+            collect_tree(&child, paths)?;
+        }
+    }
+    paths.push((path.to_path_buf(), metadata.is_dir()));
+    Ok(())
+}
+
+/// Remove a file or a whole directory tree, returning every removed path
+/// (children before their parents).
+pub fn remove_tree(path: &str, dry_run: bool) -> Result<Vec<String>, String> {
+    let mut paths: Vec<(PathBuf, bool)> = Vec::new();
+    collect_tree(Path::new(path), &mut paths)?;
+    if !dry_run {
+        for (path, is_dir) in &paths {
+            let result = if *is_dir {
+                fs::remove_dir(path)
+            } else {
+                fs::remove_file(path)
+            };
+            result.map_err(|e| format!("{}: {}", path_string(path), e))?;
+        }
+    }
+    Ok(paths.iter().map(|(path, _)| path_string(path)).collect())
+}
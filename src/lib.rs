@@ -1,12 +1,29 @@
-use crate::context::Context;
+use crate::archive::{Archive, ArchiveBytes, ArchiveEntry};
+use crate::context::{Context, WatcherKey};
+use crate::copyjob::{CopySource, JobProgress, STATUS_FAILED};
+use crate::dirent::DirEnt;
+use crate::vfs::MountSource;
+use crate::walk::{compile_glob, WalkOptions};
+use crate::watcher::{CWatchEvent, WatchCallback, WatcherOptions, WATCHER_KIND_OTHER};
 use log::{error, info, warn};
 use std::ffi::{CStr, CString};
 use std::fs;
-use std::os::raw::c_char;
+use std::os::raw::{c_char, c_void};
+use std::path::PathBuf;
 use std::ptr;
+use std::sync::Arc;
+use std::time::Duration;
 
 mod archive;
 mod context;
+mod copyjob;
+mod debounce;
+mod dirent;
+mod fileio;
+mod pathutil;
+mod reload;
+mod vfs;
+mod walk;
 mod watcher;
 
 const INVALID_HANDLE: u64 = u64::MAX;
@@ -16,6 +33,22 @@ fn c_str_to_string(s: *const c_char) -> String {
     unsafe { CStr::from_ptr(s).to_string_lossy().into_owned() }
 }
 
+fn c_str_to_string_opt(s: *const c_char) -> String {
+    if s.is_null() {
+        String::new()
+    } else {
+        c_str_to_string(s)
+    }
+}
+
+fn optional_handle<K: From<u64>>(handle: u64) -> Option<K> {
+    if handle == INVALID_HANDLE {
+        None
+    } else {
+        Some(handle.into())
+    }
+}
+
 fn c_str_to_cstring(s: *const c_char) -> CString {
     CString::new(c_str_to_string(s)).unwrap_or_default()
 }
@@ -87,6 +120,166 @@ pub unsafe extern "C" fn trussfs_recursive_makedir(_ctx: *mut Context, path: *co
     }
 }
 
+/// # Safety
+///
+/// ctx must be valid
+#[no_mangle]
+pub unsafe extern "C" fn trussfs_copy_file(
+    ctx: *mut Context,
+    src: *const c_char,
+    dst: *const c_char,
+    dry_run: bool,
+) -> u64 {
+    let ctx = &mut *ctx;
+    let src = c_str_to_string(src);
+    let dst = c_str_to_string(dst);
+    match ctx.paths_to_list(fileio::copy_file(&src, &dst, dry_run)) {
+        Some(handle) => handle.into(),
+        None => INVALID_HANDLE,
+    }
+}
+
+/// # Safety
+///
+/// ctx must be valid
+#[no_mangle]
+pub unsafe extern "C" fn trussfs_rename(
+    ctx: *mut Context,
+    src: *const c_char,
+    dst: *const c_char,
+    dry_run: bool,
+) -> u64 {
+    let ctx = &mut *ctx;
+    let src = c_str_to_string(src);
+    let dst = c_str_to_string(dst);
+    match ctx.paths_to_list(fileio::rename(&src, &dst, dry_run)) {
+        Some(handle) => handle.into(),
+        None => INVALID_HANDLE,
+    }
+}
+
+/// # Safety
+///
+/// ctx must be valid
+#[no_mangle]
+pub unsafe extern "C" fn trussfs_remove_file(
+    ctx: *mut Context,
+    path: *const c_char,
+    dry_run: bool,
+) -> u64 {
+    let ctx = &mut *ctx;
+    let path = c_str_to_string(path);
+    match ctx.paths_to_list(fileio::remove_file(&path, dry_run)) {
+        Some(handle) => handle.into(),
+        None => INVALID_HANDLE,
+    }
+}
+
+/// # Safety
+///
+/// ctx must be valid
+#[no_mangle]
+pub unsafe extern "C" fn trussfs_remove_dir(
+    ctx: *mut Context,
+    path: *const c_char,
+    dry_run: bool,
+) -> u64 {
+    let ctx = &mut *ctx;
+    let path = c_str_to_string(path);
+    match ctx.paths_to_list(fileio::remove_dir(&path, dry_run)) {
+        Some(handle) => handle.into(),
+        None => INVALID_HANDLE,
+    }
+}
+
+/// # Safety
+///
+/// ctx must be valid
+#[no_mangle]
+pub unsafe extern "C" fn trussfs_remove_tree(
+    ctx: *mut Context,
+    path: *const c_char,
+    dry_run: bool,
+) -> u64 {
+    let ctx = &mut *ctx;
+    let path = c_str_to_string(path);
+    match ctx.paths_to_list(fileio::remove_tree(&path, dry_run)) {
+        Some(handle) => handle.into(),
+        None => INVALID_HANDLE,
+    }
+}
+
+/// # Safety
+///
+/// ctx must be valid
+#[no_mangle]
+pub unsafe extern "C" fn trussfs_copy_tree(
+    ctx: *mut Context,
+    src: *const c_char,
+    dst: *const c_char,
+    policy: u32,
+) -> u64 {
+    let ctx = &mut *ctx;
+    let source = CopySource::Dir(PathBuf::from(c_str_to_string(src)));
+    let dst = c_str_to_string(dst);
+    match ctx.copy_tree(Ok(source), dst, policy) {
+        Some(handle) => handle.into(),
+        None => INVALID_HANDLE,
+    }
+}
+
+/// # Safety
+///
+/// ctx must be valid
+#[no_mangle]
+pub unsafe extern "C" fn trussfs_copy_tree_archive(
+    ctx: *mut Context,
+    archive_handle: u64,
+    prefix: *const c_char,
+    dst: *const c_char,
+    policy: u32,
+) -> u64 {
+    let ctx = &mut *ctx;
+    let source = ctx.copy_tree_archive_source(archive_handle.into(), c_str_to_string_opt(prefix));
+    let dst = c_str_to_string(dst);
+    match ctx.copy_tree(source, dst, policy) {
+        Some(handle) => handle.into(),
+        None => INVALID_HANDLE,
+    }
+}
+
+/// # Safety
+///
+/// ctx must be valid, and progress must be null or point to a
+/// trussfs_job_progress struct
+#[no_mangle]
+pub unsafe extern "C" fn trussfs_job_progress(
+    ctx: *mut Context,
+    job_handle: u64,
+    progress: *mut JobProgress,
+) -> i32 {
+    let ctx = &mut *ctx;
+    match ctx.job_progress(job_handle.into()) {
+        Some(job_progress) => {
+            let status = job_progress.status;
+            if !progress.is_null() {
+                *progress = job_progress;
+            }
+            status
+        }
+        None => STATUS_FAILED,
+    }
+}
+
+/// # Safety
+///
+/// ctx must be valid
+#[no_mangle]
+pub unsafe extern "C" fn trussfs_job_free(ctx: *mut Context, job_handle: u64) {
+    let ctx = &mut *ctx;
+    ctx.jobs.remove(job_handle.into());
+}
+
 /// # Safety
 ///
 /// ctx must be valid
@@ -124,12 +317,71 @@ pub unsafe extern "C" fn trussfs_watcher_create(
 ) -> u64 {
     let ctx = &mut *ctx;
     let path = c_str_to_string(path);
-    match ctx.watch_path(path, recursive) {
+    match ctx.watch_path(path, recursive, &WatcherOptions::default()) {
+        Some(handle) => handle.into(),
+        None => INVALID_HANDLE,
+    }
+}
+
+/// # Safety
+///
+/// ctx must be valid
+#[no_mangle]
+pub unsafe extern "C" fn trussfs_watcher_create_debounced(
+    ctx: *mut Context,
+    path: *const c_char,
+    recursive: bool,
+    debounce_ms: u32,
+) -> u64 {
+    let ctx = &mut *ctx;
+    let path = c_str_to_string(path);
+    let options = WatcherOptions {
+        debounce_ms,
+        ..Default::default()
+    };
+    match ctx.watch_path(path, recursive, &options) {
+        Some(handle) => handle.into(),
+        None => INVALID_HANDLE,
+    }
+}
+
+/// # Safety
+///
+/// ctx must be valid
+#[no_mangle]
+pub unsafe extern "C" fn trussfs_watcher_create_ex(
+    ctx: *mut Context,
+    path: *const c_char,
+    recursive: bool,
+    backend: u32,
+    poll_interval_ms: u32,
+    debounce_ms: u32,
+) -> u64 {
+    let ctx = &mut *ctx;
+    let path = c_str_to_string(path);
+    let options = WatcherOptions {
+        backend,
+        poll_interval_ms,
+        debounce_ms,
+    };
+    match ctx.watch_path(path, recursive, &options) {
         Some(handle) => handle.into(),
         None => INVALID_HANDLE,
     }
 }
 
+/// # Safety
+///
+/// ctx must be valid
+#[no_mangle]
+pub unsafe extern "C" fn trussfs_watcher_kind(ctx: *mut Context, watcher_handle: u64) -> u32 {
+    let ctx = &mut *ctx;
+    match ctx.watchers.get(watcher_handle.into()) {
+        Some(watcher) => watcher.kind(),
+        None => WATCHER_KIND_OTHER,
+    }
+}
+
 /// # Safety
 ///
 /// ctx must be valid
@@ -150,18 +402,23 @@ pub unsafe extern "C" fn trussfs_watcher_augment(
 ///
 /// ctx must be valid
 #[no_mangle]
-pub unsafe extern "C" fn trussfs_watcher_free(ctx: *mut Context, watcher_handle: u64) {
+pub unsafe extern "C" fn trussfs_watcher_remove_path(
+    ctx: *mut Context,
+    watcher_handle: u64,
+    path: *const c_char,
+) -> bool {
     let ctx = &mut *ctx;
-    ctx.watchers.remove(watcher_handle.into());
+    let path = c_str_to_string(path);
+    ctx.watch_remove(watcher_handle.into(), path)
 }
 
 /// # Safety
 ///
 /// ctx must be valid
 #[no_mangle]
-pub unsafe extern "C" fn trussfs_watcher_poll(ctx: *mut Context, watcher: u64) -> u64 {
+pub unsafe extern "C" fn trussfs_watcher_list_paths(ctx: *mut Context, watcher_handle: u64) -> u64 {
     let ctx = &mut *ctx;
-    match ctx.watcher_poll(watcher.into()) {
+    match ctx.watched_paths(watcher_handle.into()) {
         Some(handle) => handle.into(),
         None => INVALID_HANDLE,
     }
@@ -169,52 +426,56 @@ pub unsafe extern "C" fn trussfs_watcher_poll(ctx: *mut Context, watcher: u64) -
 
 /// # Safety
 ///
-/// ctx must be valid
+/// ctx must be valid; either list may be INVALID_HANDLE (or empty) to skip
+/// that side of the filter
 #[no_mangle]
-pub unsafe extern "C" fn trussfs_archive_mount(ctx: *mut Context, path: *const c_char) -> u64 {
+pub unsafe extern "C" fn trussfs_watcher_set_filter(
+    ctx: *mut Context,
+    watcher_handle: u64,
+    include_globs: u64,
+    exclude_globs: u64,
+) -> bool {
     let ctx = &mut *ctx;
-    let path = c_str_to_string(path);
-    match ctx.mount_archive(path) {
-        Some(handle) => handle.into(),
-        None => INVALID_HANDLE,
-    }
+    ctx.watcher_set_filter(
+        watcher_handle.into(),
+        optional_handle(include_globs),
+        optional_handle(exclude_globs),
+    )
 }
 
 /// # Safety
 ///
-/// ctx must be valid
+/// ctx must be valid; callback (if not null) and userdata must stay usable
+/// from the dispatch thread until the callback is replaced or unset
 #[no_mangle]
-pub unsafe extern "C" fn trussfs_archive_list(ctx: *mut Context, archive: u64) -> u64 {
+pub unsafe extern "C" fn trussfs_watcher_set_callback(
+    ctx: *mut Context,
+    watcher_handle: u64,
+    callback: Option<WatchCallback>,
+    userdata: *mut c_void,
+) -> bool {
     let ctx = &mut *ctx;
-    match ctx.list_archive(archive.into()) {
-        Some(handle) => handle.into(),
-        None => INVALID_HANDLE,
-    }
+    ctx.watcher_set_callback(watcher_handle.into(), callback, userdata)
 }
 
 /// # Safety
 ///
 /// ctx must be valid
 #[no_mangle]
-pub unsafe extern "C" fn trussfs_archive_free(ctx: *mut Context, archive_handle: u64) {
+pub unsafe extern "C" fn trussfs_watcher_free(ctx: *mut Context, watcher_handle: u64) {
     let ctx = &mut *ctx;
-    ctx.archives.remove(archive_handle.into());
+    ctx.watchers.remove(watcher_handle.into());
 }
 
 /// # Safety
 ///
 /// ctx must be valid
 #[no_mangle]
-pub unsafe extern "C" fn trussfs_archive_filesize_name(
-    ctx: *mut Context,
-    archive_handle: u64,
-    name: *const c_char,
-) -> u64 {
+pub unsafe extern "C" fn trussfs_watcher_poll_errors(ctx: *mut Context, watcher: u64) -> u64 {
     let ctx = &mut *ctx;
-    let name = c_str_to_string(name);
-    match ctx.archives.get_mut(archive_handle.into()) {
-        Some(archive) => archive.filesize_by_name(name).unwrap_or_default(),
-        None => 0,
+    match ctx.watcher_poll_errors(watcher.into()) {
+        Some(handle) => handle.into(),
+        None => INVALID_HANDLE,
     }
 }
 
@@ -222,86 +483,59 @@ pub unsafe extern "C" fn trussfs_archive_filesize_name(
 ///
 /// ctx must be valid
 #[no_mangle]
-pub unsafe extern "C" fn trussfs_archive_filesize_index(
-    ctx: *mut Context,
-    archive_handle: u64,
-    index: u64,
-) -> u64 {
+pub unsafe extern "C" fn trussfs_watcher_is_healthy(ctx: *mut Context, watcher: u64) -> bool {
     let ctx = &mut *ctx;
-    match ctx.archives.get_mut(archive_handle.into()) {
-        Some(archive) => archive
-            .filesize_by_index(index as usize)
-            .unwrap_or_default(),
-        None => 0,
+    match ctx.watchers.get_mut(watcher.into()) {
+        Some(watcher) => watcher.is_healthy(),
+        None => false,
     }
 }
 
-unsafe fn copy_data(data: Vec<u8>, dest: *mut u8, dest_size: u64) -> i64 {
-    let ncopy = data.len();
-    if ncopy > dest_size as usize {
-        return -1;
-    }
-    ptr::copy_nonoverlapping(data.as_ptr(), dest, ncopy);
-    ncopy as i64
+fn wait_timeout(timeout_ms: i32) -> Option<Duration> {
+    // negative waits forever, like poll(2)
+    u64::try_from(timeout_ms).ok().map(Duration::from_millis)
 }
 
 /// # Safety
 ///
 /// ctx must be valid
 #[no_mangle]
-pub unsafe extern "C" fn trussfs_archive_read_name(
+pub unsafe extern "C" fn trussfs_watcher_wait(
     ctx: *mut Context,
-    archive_handle: u64,
-    name: *const c_char,
-    dest: *mut u8,
-    dest_size: u64,
-) -> i64 {
+    watcher_handle: u64,
+    timeout_ms: i32,
+) -> bool {
     let ctx = &mut *ctx;
-    let archive = match ctx.archives.get_mut(archive_handle.into()) {
-        Some(archive) => archive,
-        None => return -1,
-    };
-    match archive.read_file_by_name(c_str_to_string(name)) {
-        Ok(data) => copy_data(data, dest, dest_size),
-        Err(_) => -1,
-    }
+    ctx.watcher_wait(&[watcher_handle.into()], wait_timeout(timeout_ms)) == 0
 }
 
 /// # Safety
 ///
-/// ctx must be valid
+/// ctx must be valid, watchers must point to count handles
 #[no_mangle]
-pub unsafe extern "C" fn trussfs_archive_read_index(
+pub unsafe extern "C" fn trussfs_watcher_wait_any(
     ctx: *mut Context,
-    archive_handle: u64,
-    index: u64,
-    dest: *mut u8,
-    dest_size: u64,
+    watchers: *const u64,
+    count: u64,
+    timeout_ms: i32,
 ) -> i64 {
     let ctx = &mut *ctx;
-    let archive = match ctx.archives.get_mut(archive_handle.into()) {
-        Some(archive) => archive,
-        None => return -1,
+    let handles: &[u64] = if watchers.is_null() || count == 0 {
+        &[]
+    } else {
+        std::slice::from_raw_parts(watchers, count as usize)
     };
-    match archive.read_file_by_index(index as usize) {
-        Ok(data) => copy_data(data, dest, dest_size),
-        Err(_) => -1,
-    }
+    let keys: Vec<WatcherKey> = handles.iter().map(|&handle| handle.into()).collect();
+    ctx.watcher_wait(&keys, wait_timeout(timeout_ms))
 }
 
 /// # Safety
 ///
 /// ctx must be valid
 #[no_mangle]
-pub unsafe extern "C" fn trussfs_list_dir(
-    ctx: *mut Context,
-    path: *const c_char,
-    files_only: bool,
-    include_metadata: bool,
-) -> u64 {
+pub unsafe extern "C" fn trussfs_watcher_poll(ctx: *mut Context, watcher: u64) -> u64 {
     let ctx = &mut *ctx;
-    let path = c_str_to_string(path);
-    match ctx.listdir(path, files_only, include_metadata) {
+    match ctx.watcher_poll(watcher.into()) {
         Some(handle) => handle.into(),
         None => INVALID_HANDLE,
     }
@@ -311,15 +545,1209 @@ pub unsafe extern "C" fn trussfs_list_dir(
 ///
 /// ctx must be valid
 #[no_mangle]
-pub unsafe extern "C" fn trussfs_split_path(ctx: *mut Context, path: *const c_char) -> u64 {
+pub unsafe extern "C" fn trussfs_watcher_poll_ex(ctx: *mut Context, watcher: u64) -> u64 {
     let ctx = &mut *ctx;
-    let path = c_str_to_string(path);
-    match ctx.splitpath(path) {
+    match ctx.watcher_poll_ex(watcher.into()) {
         Some(handle) => handle.into(),
         None => INVALID_HANDLE,
     }
 }
 
+/// # Safety
+///
+/// ctx must be valid
+#[no_mangle]
+pub unsafe extern "C" fn trussfs_eventlist_free(ctx: *mut Context, list_handle: u64) {
+    let ctx = &mut *ctx;
+    ctx.eventlists.remove(list_handle.into());
+}
+
+/// # Safety
+///
+/// ctx must be valid
+#[no_mangle]
+pub unsafe extern "C" fn trussfs_eventlist_length(ctx: *mut Context, list_handle: u64) -> u64 {
+    let ctx = &mut *ctx;
+    match ctx.eventlists.get(list_handle.into()) {
+        Some(list) => list.len() as u64,
+        None => {
+            warn!("Event list {} does not exist.", list_handle);
+            0
+        }
+    }
+}
+
+/// # Safety
+///
+/// ctx must be valid; the returned event is only valid until the list is freed
+#[no_mangle]
+pub unsafe extern "C" fn trussfs_eventlist_get(
+    ctx: *mut Context,
+    list_handle: u64,
+    list_index: u64,
+) -> *const CWatchEvent {
+    let ctx = &mut *ctx;
+    let list = match ctx.eventlists.get(list_handle.into()) {
+        Some(list) => list,
+        None => {
+            warn!("Event list {} does not exist.", list_handle);
+            return ptr::null();
+        }
+    };
+    match list.get(list_index as usize) {
+        Some(event) => event,
+        None => {
+            warn!(
+                "Index {} out of range for event list size {}",
+                list_index,
+                list.len()
+            );
+            ptr::null()
+        }
+    }
+}
+
+/// # Safety
+///
+/// ctx must be valid
+#[no_mangle]
+pub unsafe extern "C" fn trussfs_reload_create(ctx: *mut Context, debounce_ms: u32) -> u64 {
+    let ctx = &mut *ctx;
+    match ctx.reload_create(debounce_ms) {
+        Some(handle) => handle.into(),
+        None => INVALID_HANDLE,
+    }
+}
+
+/// # Safety
+///
+/// ctx must be valid
+#[no_mangle]
+pub unsafe extern "C" fn trussfs_reload_free(ctx: *mut Context, tracker_handle: u64) {
+    let ctx = &mut *ctx;
+    ctx.reload_trackers.remove(tracker_handle.into());
+}
+
+/// # Safety
+///
+/// ctx must be valid
+#[no_mangle]
+pub unsafe extern "C" fn trussfs_reload_track_file(
+    ctx: *mut Context,
+    tracker_handle: u64,
+    name: *const c_char,
+    path: *const c_char,
+) -> bool {
+    let ctx = &mut *ctx;
+    let name = c_str_to_string(name);
+    let path = c_str_to_string(path);
+    let tracked = ctx.reload_track_err(tracker_handle.into(), name, Some(path));
+    ctx.reload_track(tracked)
+}
+
+/// # Safety
+///
+/// ctx must be valid
+#[no_mangle]
+pub unsafe extern "C" fn trussfs_reload_track_vfs(
+    ctx: *mut Context,
+    tracker_handle: u64,
+    vpath: *const c_char,
+) -> bool {
+    let ctx = &mut *ctx;
+    let vpath = c_str_to_string(vpath);
+    let tracked = ctx.reload_track_err(tracker_handle.into(), vpath, None);
+    ctx.reload_track(tracked)
+}
+
+/// # Safety
+///
+/// ctx must be valid
+#[no_mangle]
+pub unsafe extern "C" fn trussfs_reload_track_archive(
+    ctx: *mut Context,
+    tracker_handle: u64,
+    name: *const c_char,
+    archive_handle: u64,
+) -> bool {
+    let ctx = &mut *ctx;
+    let name = c_str_to_string(name);
+    let tracked = ctx.reload_track_archive_err(tracker_handle.into(), name, archive_handle.into());
+    ctx.reload_track(tracked)
+}
+
+/// # Safety
+///
+/// ctx must be valid
+#[no_mangle]
+pub unsafe extern "C" fn trussfs_reload_untrack(
+    ctx: *mut Context,
+    tracker_handle: u64,
+    name: *const c_char,
+) -> bool {
+    let ctx = &mut *ctx;
+    let name = c_str_to_string(name);
+    match ctx.reload_trackers.get_mut(tracker_handle.into()) {
+        Some(tracker) => tracker.untrack(&name),
+        None => false,
+    }
+}
+
+/// # Safety
+///
+/// ctx must be valid
+#[no_mangle]
+pub unsafe extern "C" fn trussfs_reload_poll(ctx: *mut Context, tracker_handle: u64) -> u64 {
+    let ctx = &mut *ctx;
+    match ctx.reload_poll(tracker_handle.into()) {
+        Some(handle) => handle.into(),
+        None => INVALID_HANDLE,
+    }
+}
+
+/// # Safety
+///
+/// ctx must be valid
+#[no_mangle]
+pub unsafe extern "C" fn trussfs_archive_mount(ctx: *mut Context, path: *const c_char) -> u64 {
+    let ctx = &mut *ctx;
+    let path = c_str_to_string(path);
+    match ctx.mount_archive(path) {
+        Some(handle) => handle.into(),
+        None => INVALID_HANDLE,
+    }
+}
+
+/// # Safety
+///
+/// ctx must be valid
+#[no_mangle]
+pub unsafe extern "C" fn trussfs_archive_mount_mapped(
+    ctx: *mut Context,
+    path: *const c_char,
+) -> u64 {
+    let ctx = &mut *ctx;
+    let path = c_str_to_string(path);
+    match ctx.insert_archive(Archive::open_mapped(path)) {
+        Some(handle) => handle.into(),
+        None => INVALID_HANDLE,
+    }
+}
+
+/// # Safety
+///
+/// ctx must be valid
+#[no_mangle]
+pub unsafe extern "C" fn trussfs_archive_mount_nested(
+    ctx: *mut Context,
+    parent: u64,
+    entry_name: *const c_char,
+) -> u64 {
+    let ctx = &mut *ctx;
+    let entry_name = c_str_to_string(entry_name);
+    match ctx.mount_archive_nested(parent.into(), entry_name) {
+        Some(handle) => handle.into(),
+        None => INVALID_HANDLE,
+    }
+}
+
+/// # Safety
+///
+/// ctx must be valid, data must point to len bytes; without copy, the data
+/// must stay alive and unchanged until the archive and every reader or copy
+/// job opened from it has been freed
+#[no_mangle]
+pub unsafe extern "C" fn trussfs_archive_mount_memory(
+    ctx: *mut Context,
+    data: *const u8,
+    len: u64,
+    copy: bool,
+) -> u64 {
+    let ctx = &mut *ctx;
+    let data = data_slice(data, len);
+    let bytes = if copy {
+        ArchiveBytes::Owned(Arc::from(data))
+    } else {
+        ArchiveBytes::Borrowed(data.as_ptr(), data.len())
+    };
+    match ctx.insert_archive(Archive::from_memory(bytes)) {
+        Some(handle) => handle.into(),
+        None => INVALID_HANDLE,
+    }
+}
+
+/// # Safety
+///
+/// ctx must be valid
+#[no_mangle]
+pub unsafe extern "C" fn trussfs_archive_set_password(
+    ctx: *mut Context,
+    archive_handle: u64,
+    password: *const c_char,
+) -> bool {
+    let ctx = &mut *ctx;
+    let password = if password.is_null() {
+        None
+    } else {
+        Some(CStr::from_ptr(password).to_bytes().to_vec())
+    };
+    ctx.archive_set_password(archive_handle.into(), password)
+}
+
+/// # Safety
+///
+/// ctx must be valid
+#[no_mangle]
+pub unsafe extern "C" fn trussfs_archive_set_auto_reload(
+    ctx: *mut Context,
+    archive_handle: u64,
+    enabled: bool,
+) -> bool {
+    let ctx = &mut *ctx;
+    ctx.archive_set_auto_reload(archive_handle.into(), enabled)
+}
+
+/// # Safety
+///
+/// ctx must be valid
+#[no_mangle]
+pub unsafe extern "C" fn trussfs_archive_generation(ctx: *mut Context, archive_handle: u64) -> u64 {
+    let ctx = &mut *ctx;
+    match ctx.archives.get_mut(archive_handle.into()) {
+        Some(archive) => {
+            archive.refresh();
+            archive.generation()
+        }
+        None => 0,
+    }
+}
+
+/// # Safety
+///
+/// ctx must be valid
+#[no_mangle]
+pub unsafe extern "C" fn trussfs_archive_list(ctx: *mut Context, archive: u64) -> u64 {
+    let ctx = &mut *ctx;
+    match ctx.list_archive(archive.into()) {
+        Some(handle) => handle.into(),
+        None => INVALID_HANDLE,
+    }
+}
+
+/// # Safety
+///
+/// ctx must be valid
+#[no_mangle]
+pub unsafe extern "C" fn trussfs_archive_list_dir(
+    ctx: *mut Context,
+    archive: u64,
+    dir: *const c_char,
+    files_only: bool,
+) -> u64 {
+    let ctx = &mut *ctx;
+    let dir = c_str_to_string_opt(dir);
+    match ctx.archive_list_dir(archive.into(), dir, files_only) {
+        Some(handle) => handle.into(),
+        None => INVALID_HANDLE,
+    }
+}
+
+/// # Safety
+///
+/// ctx must be valid
+#[no_mangle]
+pub unsafe extern "C" fn trussfs_archive_exists(
+    ctx: *mut Context,
+    archive: u64,
+    path: *const c_char,
+) -> bool {
+    let ctx = &mut *ctx;
+    let path = c_str_to_string(path);
+    match ctx.archives.get_mut(archive.into()) {
+        Some(archive) => archive.exists(&path),
+        None => false,
+    }
+}
+
+/// # Safety
+///
+/// ctx must be valid
+#[no_mangle]
+pub unsafe extern "C" fn trussfs_archive_list_ex(ctx: *mut Context, archive: u64) -> u64 {
+    let ctx = &mut *ctx;
+    match ctx.list_archive_ex(archive.into()) {
+        Some(handle) => handle.into(),
+        None => INVALID_HANDLE,
+    }
+}
+
+/// # Safety
+///
+/// ctx must be valid
+#[no_mangle]
+pub unsafe extern "C" fn trussfs_entrylist_free(ctx: *mut Context, list_handle: u64) {
+    let ctx = &mut *ctx;
+    ctx.entrylists.remove(list_handle.into());
+}
+
+/// # Safety
+///
+/// ctx must be valid
+#[no_mangle]
+pub unsafe extern "C" fn trussfs_entrylist_length(ctx: *mut Context, list_handle: u64) -> u64 {
+    let ctx = &mut *ctx;
+    match ctx.entrylists.get(list_handle.into()) {
+        Some(list) => list.len() as u64,
+        None => {
+            warn!("Entry list {} does not exist.", list_handle);
+            0
+        }
+    }
+}
+
+fn archive_entry(ctx: &Context, list_handle: u64, list_index: u64) -> Option<&ArchiveEntry> {
+    let list = match ctx.entrylists.get(list_handle.into()) {
+        Some(list) => list,
+        None => {
+            warn!("Entry list {} does not exist.", list_handle);
+            return None;
+        }
+    };
+    let entry = list.get(list_index as usize);
+    if entry.is_none() {
+        warn!(
+            "Index {} is out of bounds for entry list {}",
+            list_index, list_handle
+        );
+    }
+    entry
+}
+
+/// # Safety
+///
+/// ctx must be valid; the returned string is only valid until the list is freed
+#[no_mangle]
+pub unsafe extern "C" fn trussfs_entrylist_name(
+    ctx: *mut Context,
+    list_handle: u64,
+    list_index: u64,
+) -> *const c_char {
+    let ctx = &mut *ctx;
+    match archive_entry(ctx, list_handle, list_index) {
+        Some(entry) => entry.name.as_ptr(),
+        None => ptr::null(),
+    }
+}
+
+/// # Safety
+///
+/// ctx must be valid; the returned string is only valid until the list is freed
+#[no_mangle]
+pub unsafe extern "C" fn trussfs_entrylist_comment(
+    ctx: *mut Context,
+    list_handle: u64,
+    list_index: u64,
+) -> *const c_char {
+    let ctx = &mut *ctx;
+    match archive_entry(ctx, list_handle, list_index) {
+        Some(entry) => entry.comment.as_ptr(),
+        None => ptr::null(),
+    }
+}
+
+/// # Safety
+///
+/// ctx must be valid
+#[no_mangle]
+pub unsafe extern "C" fn trussfs_entrylist_index(
+    ctx: *mut Context,
+    list_handle: u64,
+    list_index: u64,
+) -> u64 {
+    let ctx = &mut *ctx;
+    archive_entry(ctx, list_handle, list_index).map_or(0, |entry| entry.index)
+}
+
+/// # Safety
+///
+/// ctx must be valid
+#[no_mangle]
+pub unsafe extern "C" fn trussfs_entrylist_is_dir(
+    ctx: *mut Context,
+    list_handle: u64,
+    list_index: u64,
+) -> bool {
+    let ctx = &mut *ctx;
+    archive_entry(ctx, list_handle, list_index).is_some_and(|entry| entry.is_dir)
+}
+
+/// # Safety
+///
+/// ctx must be valid
+#[no_mangle]
+pub unsafe extern "C" fn trussfs_entrylist_is_unsafe(
+    ctx: *mut Context,
+    list_handle: u64,
+    list_index: u64,
+) -> bool {
+    let ctx = &mut *ctx;
+    archive_entry(ctx, list_handle, list_index).is_some_and(|entry| entry.is_unsafe)
+}
+
+/// # Safety
+///
+/// ctx must be valid
+#[no_mangle]
+pub unsafe extern "C" fn trussfs_entrylist_is_encrypted(
+    ctx: *mut Context,
+    list_handle: u64,
+    list_index: u64,
+) -> bool {
+    let ctx = &mut *ctx;
+    archive_entry(ctx, list_handle, list_index).is_some_and(|entry| entry.is_encrypted)
+}
+
+/// # Safety
+///
+/// ctx must be valid
+#[no_mangle]
+pub unsafe extern "C" fn trussfs_entrylist_size(
+    ctx: *mut Context,
+    list_handle: u64,
+    list_index: u64,
+) -> u64 {
+    let ctx = &mut *ctx;
+    archive_entry(ctx, list_handle, list_index).map_or(0, |entry| entry.size)
+}
+
+/// # Safety
+///
+/// ctx must be valid
+#[no_mangle]
+pub unsafe extern "C" fn trussfs_entrylist_compressed_size(
+    ctx: *mut Context,
+    list_handle: u64,
+    list_index: u64,
+) -> u64 {
+    let ctx = &mut *ctx;
+    archive_entry(ctx, list_handle, list_index).map_or(0, |entry| entry.compressed_size)
+}
+
+/// # Safety
+///
+/// ctx must be valid
+#[no_mangle]
+pub unsafe extern "C" fn trussfs_entrylist_compression(
+    ctx: *mut Context,
+    list_handle: u64,
+    list_index: u64,
+) -> u32 {
+    let ctx = &mut *ctx;
+    archive_entry(ctx, list_handle, list_index).map_or(0, |entry| entry.compression)
+}
+
+/// # Safety
+///
+/// ctx must be valid
+#[no_mangle]
+pub unsafe extern "C" fn trussfs_entrylist_crc32(
+    ctx: *mut Context,
+    list_handle: u64,
+    list_index: u64,
+) -> u32 {
+    let ctx = &mut *ctx;
+    archive_entry(ctx, list_handle, list_index).map_or(0, |entry| entry.crc32)
+}
+
+/// # Safety
+///
+/// ctx must be valid
+#[no_mangle]
+pub unsafe extern "C" fn trussfs_entrylist_mtime(
+    ctx: *mut Context,
+    list_handle: u64,
+    list_index: u64,
+) -> f64 {
+    let ctx = &mut *ctx;
+    archive_entry(ctx, list_handle, list_index).map_or(0.0, |entry| entry.mtime)
+}
+
+/// # Safety
+///
+/// ctx must be valid
+#[no_mangle]
+pub unsafe extern "C" fn trussfs_entrylist_unix_mode(
+    ctx: *mut Context,
+    list_handle: u64,
+    list_index: u64,
+) -> u32 {
+    let ctx = &mut *ctx;
+    archive_entry(ctx, list_handle, list_index).map_or(0, |entry| entry.unix_mode)
+}
+
+/// # Safety
+///
+/// ctx must be valid
+#[no_mangle]
+pub unsafe extern "C" fn trussfs_archive_free(ctx: *mut Context, archive_handle: u64) {
+    let ctx = &mut *ctx;
+    ctx.archives.remove(archive_handle.into());
+}
+
+/// # Safety
+///
+/// ctx must be valid
+#[no_mangle]
+pub unsafe extern "C" fn trussfs_archive_filesize_name(
+    ctx: *mut Context,
+    archive_handle: u64,
+    name: *const c_char,
+) -> u64 {
+    let ctx = &mut *ctx;
+    let name = c_str_to_string(name);
+    match ctx.archives.get_mut(archive_handle.into()) {
+        Some(archive) => archive.filesize_by_name(name).unwrap_or_default(),
+        None => 0,
+    }
+}
+
+/// # Safety
+///
+/// ctx must be valid
+#[no_mangle]
+pub unsafe extern "C" fn trussfs_archive_filesize_index(
+    ctx: *mut Context,
+    archive_handle: u64,
+    index: u64,
+) -> u64 {
+    let ctx = &mut *ctx;
+    match ctx.archives.get_mut(archive_handle.into()) {
+        Some(archive) => archive
+            .filesize_by_index(index as usize)
+            .unwrap_or_default(),
+        None => 0,
+    }
+}
+
+unsafe fn data_slice<'a>(data: *const u8, len: u64) -> &'a [u8] {
+    if data.is_null() || len == 0 {
+        &[]
+    } else {
+        std::slice::from_raw_parts(data, len as usize)
+    }
+}
+
+unsafe fn dest_slice<'a>(dest: *mut u8, dest_size: u64) -> &'a mut [u8] {
+    if dest.is_null() || dest_size == 0 {
+        &mut []
+    } else {
+        std::slice::from_raw_parts_mut(dest, dest_size as usize)
+    }
+}
+
+unsafe fn copy_data(data: Vec<u8>, dest: *mut u8, dest_size: u64) -> i64 {
+    let ncopy = data.len();
+    if ncopy > dest_size as usize {
+        return -1;
+    }
+    ptr::copy_nonoverlapping(data.as_ptr(), dest, ncopy);
+    ncopy as i64
+}
+
+/// # Safety
+///
+/// ctx must be valid
+#[no_mangle]
+pub unsafe extern "C" fn trussfs_archive_read_name(
+    ctx: *mut Context,
+    archive_handle: u64,
+    name: *const c_char,
+    dest: *mut u8,
+    dest_size: u64,
+) -> i64 {
+    let ctx = &mut *ctx;
+    let archive = match ctx.archives.get_mut(archive_handle.into()) {
+        Some(archive) => archive,
+        None => return -1,
+    };
+    match archive.read_file_by_name(c_str_to_string(name)) {
+        Ok(data) => copy_data(data, dest, dest_size),
+        Err(_) => -1,
+    }
+}
+
+/// # Safety
+///
+/// ctx must be valid
+#[no_mangle]
+pub unsafe extern "C" fn trussfs_archive_read_index(
+    ctx: *mut Context,
+    archive_handle: u64,
+    index: u64,
+    dest: *mut u8,
+    dest_size: u64,
+) -> i64 {
+    let ctx = &mut *ctx;
+    let archive = match ctx.archives.get_mut(archive_handle.into()) {
+        Some(archive) => archive,
+        None => return -1,
+    };
+    match archive.read_file_by_index(index as usize) {
+        Ok(data) => copy_data(data, dest, dest_size),
+        Err(_) => -1,
+    }
+}
+
+/// # Safety
+///
+/// ctx must be valid
+#[no_mangle]
+pub unsafe extern "C" fn trussfs_archive_open_entry(
+    ctx: *mut Context,
+    archive_handle: u64,
+    name: *const c_char,
+) -> u64 {
+    let ctx = &mut *ctx;
+    let name = c_str_to_string(name);
+    match ctx.open_archive_entry(archive_handle.into(), name) {
+        Some(handle) => handle.into(),
+        None => INVALID_HANDLE,
+    }
+}
+
+/// # Safety
+///
+/// ctx must be valid, and dest must point to at least dest_size writable bytes
+#[no_mangle]
+pub unsafe extern "C" fn trussfs_reader_read(
+    ctx: *mut Context,
+    reader_handle: u64,
+    dest: *mut u8,
+    dest_size: u64,
+) -> i64 {
+    let ctx = &mut *ctx;
+    if dest.is_null() {
+        ctx.last_error = CString::new("Null destination buffer").unwrap();
+        return -1;
+    }
+    let dest = dest_slice(dest, dest_size);
+    match ctx.reader_read(reader_handle.into(), dest) {
+        Some(n) => n as i64,
+        None => -1,
+    }
+}
+
+/// # Safety
+///
+/// ctx must be valid
+#[no_mangle]
+pub unsafe extern "C" fn trussfs_reader_close(ctx: *mut Context, reader_handle: u64) {
+    let ctx = &mut *ctx;
+    ctx.readers.remove(reader_handle.into());
+}
+
+/// # Safety
+///
+/// ctx must be valid
+#[no_mangle]
+pub unsafe extern "C" fn trussfs_archive_create(ctx: *mut Context, path: *const c_char) -> u64 {
+    let ctx = &mut *ctx;
+    let path = c_str_to_string(path);
+    match ctx.create_archive(path) {
+        Some(handle) => handle.into(),
+        None => INVALID_HANDLE,
+    }
+}
+
+/// # Safety
+///
+/// ctx must be valid, and data must point to at least len readable bytes
+#[no_mangle]
+pub unsafe extern "C" fn trussfs_archive_add_file(
+    ctx: *mut Context,
+    writer_handle: u64,
+    name: *const c_char,
+    data: *const u8,
+    len: u64,
+    compression_level: i32,
+) -> bool {
+    let ctx = &mut *ctx;
+    let name = c_str_to_string(name);
+    let data = data_slice(data, len);
+    ctx.archive_add_file(writer_handle.into(), name, data, compression_level)
+}
+
+/// # Safety
+///
+/// ctx must be valid
+#[no_mangle]
+pub unsafe extern "C" fn trussfs_archive_add_dir(
+    ctx: *mut Context,
+    writer_handle: u64,
+    name: *const c_char,
+) -> bool {
+    let ctx = &mut *ctx;
+    let name = c_str_to_string(name);
+    ctx.archive_add_dir(writer_handle.into(), name)
+}
+
+/// # Safety
+///
+/// ctx must be valid
+#[no_mangle]
+pub unsafe extern "C" fn trussfs_archive_finish(ctx: *mut Context, writer_handle: u64) -> bool {
+    let ctx = &mut *ctx;
+    ctx.archive_finish(writer_handle.into())
+}
+
+/// # Safety
+///
+/// ctx must be valid
+#[no_mangle]
+pub unsafe extern "C" fn trussfs_file_size(ctx: *mut Context, path: *const c_char) -> u64 {
+    let ctx = &mut *ctx;
+    let path = c_str_to_string(path);
+    ctx.file_size(path).unwrap_or_default()
+}
+
+/// # Safety
+///
+/// ctx must be valid, and dest must point to at least dest_size writable bytes
+#[no_mangle]
+pub unsafe extern "C" fn trussfs_read_file(
+    ctx: *mut Context,
+    path: *const c_char,
+    dest: *mut u8,
+    dest_size: u64,
+) -> i64 {
+    let ctx = &mut *ctx;
+    let path = c_str_to_string(path);
+    let dest = dest_slice(dest, dest_size);
+    match ctx.read_file(path, dest) {
+        Some(n) => n as i64,
+        None => -1,
+    }
+}
+
+/// # Safety
+///
+/// ctx must be valid, and data must point to at least len readable bytes
+#[no_mangle]
+pub unsafe extern "C" fn trussfs_write_file(
+    ctx: *mut Context,
+    path: *const c_char,
+    data: *const u8,
+    len: u64,
+) -> bool {
+    let ctx = &mut *ctx;
+    let path = c_str_to_string(path);
+    ctx.write_file(path, data_slice(data, len), false)
+}
+
+/// # Safety
+///
+/// ctx must be valid, and data must point to at least len readable bytes
+#[no_mangle]
+pub unsafe extern "C" fn trussfs_write_file_atomic(
+    ctx: *mut Context,
+    path: *const c_char,
+    data: *const u8,
+    len: u64,
+) -> bool {
+    let ctx = &mut *ctx;
+    let path = c_str_to_string(path);
+    ctx.write_file_atomic(path, data_slice(data, len))
+}
+
+/// # Safety
+///
+/// ctx must be valid, and data must point to at least len readable bytes
+#[no_mangle]
+pub unsafe extern "C" fn trussfs_append_file(
+    ctx: *mut Context,
+    path: *const c_char,
+    data: *const u8,
+    len: u64,
+) -> bool {
+    let ctx = &mut *ctx;
+    let path = c_str_to_string(path);
+    ctx.write_file(path, data_slice(data, len), true)
+}
+
+/// # Safety
+///
+/// ctx must be valid
+#[no_mangle]
+pub unsafe extern "C" fn trussfs_list_dir(
+    ctx: *mut Context,
+    path: *const c_char,
+    files_only: bool,
+    include_metadata: bool,
+) -> u64 {
+    let ctx = &mut *ctx;
+    let path = c_str_to_string(path);
+    match ctx.listdir(path, files_only, include_metadata) {
+        Some(handle) => handle.into(),
+        None => INVALID_HANDLE,
+    }
+}
+
+/// # Safety
+///
+/// ctx must be valid
+#[no_mangle]
+pub unsafe extern "C" fn trussfs_vfs_mount(
+    ctx: *mut Context,
+    prefix: *const c_char,
+    path: *const c_char,
+    priority: i64,
+) -> u64 {
+    let ctx = &mut *ctx;
+    let prefix = c_str_to_string(prefix);
+    let source = MountSource::Dir(PathBuf::from(c_str_to_string(path)));
+    match ctx.vfs_mount(prefix, source, priority) {
+        Some(handle) => handle.into(),
+        None => INVALID_HANDLE,
+    }
+}
+
+/// # Safety
+///
+/// ctx must be valid
+#[no_mangle]
+pub unsafe extern "C" fn trussfs_vfs_mount_archive(
+    ctx: *mut Context,
+    prefix: *const c_char,
+    archive_handle: u64,
+    priority: i64,
+) -> u64 {
+    let ctx = &mut *ctx;
+    let prefix = c_str_to_string(prefix);
+    let source = MountSource::Archive(archive_handle.into());
+    match ctx.vfs_mount(prefix, source, priority) {
+        Some(handle) => handle.into(),
+        None => INVALID_HANDLE,
+    }
+}
+
+/// # Safety
+///
+/// ctx must be valid
+#[no_mangle]
+pub unsafe extern "C" fn trussfs_vfs_unmount(ctx: *mut Context, mount_handle: u64) {
+    let ctx = &mut *ctx;
+    ctx.vfs.unmount(mount_handle.into());
+}
+
+/// # Safety
+///
+/// ctx must be valid
+#[no_mangle]
+pub unsafe extern "C" fn trussfs_vfs_filesize(ctx: *mut Context, path: *const c_char) -> u64 {
+    let ctx = &mut *ctx;
+    let path = c_str_to_string(path);
+    ctx.vfs_filesize(path).unwrap_or_default()
+}
+
+/// # Safety
+///
+/// ctx must be valid
+#[no_mangle]
+pub unsafe extern "C" fn trussfs_vfs_read(
+    ctx: *mut Context,
+    path: *const c_char,
+    dest: *mut u8,
+    dest_size: u64,
+) -> i64 {
+    let ctx = &mut *ctx;
+    if dest.is_null() {
+        ctx.last_error = CString::new("Null destination buffer").unwrap();
+        return -1;
+    }
+    let path = c_str_to_string(path);
+    match ctx.vfs_read(path) {
+        Some(data) => copy_data(data, dest, dest_size),
+        None => -1,
+    }
+}
+
+/// # Safety
+///
+/// ctx must be valid
+#[no_mangle]
+pub unsafe extern "C" fn trussfs_vfs_list(
+    ctx: *mut Context,
+    path: *const c_char,
+    files_only: bool,
+) -> u64 {
+    let ctx = &mut *ctx;
+    let path = c_str_to_string(path);
+    match ctx.vfs_list(path, files_only) {
+        Some(handle) => handle.into(),
+        None => INVALID_HANDLE,
+    }
+}
+
+/// # Safety
+///
+/// ctx must be valid
+#[no_mangle]
+pub unsafe extern "C" fn trussfs_vfs_exists(ctx: *mut Context, path: *const c_char) -> bool {
+    let ctx = &mut *ctx;
+    let path = c_str_to_string(path);
+    ctx.refresh_archives();
+    ctx.vfs.exists(&path, &ctx.archives)
+}
+
+/// # Safety
+///
+/// ctx must be valid
+#[no_mangle]
+pub unsafe extern "C" fn trussfs_list_dir_ex(
+    ctx: *mut Context,
+    path: *const c_char,
+    files_only: bool,
+) -> u64 {
+    let ctx = &mut *ctx;
+    let path = c_str_to_string(path);
+    match ctx.listdir_ex(path, files_only) {
+        Some(handle) => handle.into(),
+        None => INVALID_HANDLE,
+    }
+}
+
+/// # Safety
+///
+/// ctx must be valid
+#[no_mangle]
+pub unsafe extern "C" fn trussfs_dirlist_free(ctx: *mut Context, list_handle: u64) {
+    let ctx = &mut *ctx;
+    ctx.dirlists.remove(list_handle.into());
+}
+
+/// # Safety
+///
+/// ctx must be valid
+#[no_mangle]
+pub unsafe extern "C" fn trussfs_dirlist_length(ctx: *mut Context, list_handle: u64) -> u64 {
+    let ctx = &mut *ctx;
+    match ctx.dirlists.get(list_handle.into()) {
+        Some(list) => list.len() as u64,
+        None => {
+            warn!("Directory list {} does not exist.", list_handle);
+            0
+        }
+    }
+}
+
+/// # Safety
+///
+/// ctx must be valid; the returned entry is only valid until the list is freed
+#[no_mangle]
+pub unsafe extern "C" fn trussfs_dirlist_get(
+    ctx: *mut Context,
+    list_handle: u64,
+    list_index: u64,
+) -> *const DirEnt {
+    let ctx = &mut *ctx;
+    let list = match ctx.dirlists.get(list_handle.into()) {
+        Some(list) => list,
+        None => {
+            warn!("Directory list {} does not exist.", list_handle);
+            return ptr::null();
+        }
+    };
+    match list.get(list_index as usize) {
+        Some(entry) => entry,
+        None => {
+            warn!(
+                "Index {} out of range for directory list size {}",
+                list_index,
+                list.len()
+            );
+            ptr::null()
+        }
+    }
+}
+
+/// # Safety
+///
+/// ctx must be valid; the globs may be null or empty to disable filtering
+#[no_mangle]
+pub unsafe extern "C" fn trussfs_walk_dir(
+    ctx: *mut Context,
+    path: *const c_char,
+    include_glob: *const c_char,
+    exclude_glob: *const c_char,
+    max_depth: u32,
+    follow_symlinks: bool,
+) -> u64 {
+    let ctx = &mut *ctx;
+    let path = c_str_to_string(path);
+    let globs = (
+        compile_glob(&c_str_to_string_opt(include_glob)),
+        compile_glob(&c_str_to_string_opt(exclude_glob)),
+    );
+    let (include, exclude) = match globs {
+        (Ok(include), Ok(exclude)) => (include, exclude),
+        (Err(s), _) | (_, Err(s)) => {
+            ctx.last_error = CString::new(s).unwrap();
+            return INVALID_HANDLE;
+        }
+    };
+    let options = WalkOptions {
+        include,
+        exclude,
+        max_depth,
+        follow_symlinks,
+    };
+    match ctx.walkdir(path, options) {
+        Some(handle) => handle.into(),
+        None => INVALID_HANDLE,
+    }
+}
+
+/// # Safety
+///
+/// ctx must be valid
+#[no_mangle]
+pub unsafe extern "C" fn trussfs_split_path(ctx: *mut Context, path: *const c_char) -> u64 {
+    let ctx = &mut *ctx;
+    let path = c_str_to_string(path);
+    match ctx.splitpath(path) {
+        Some(handle) => handle.into(),
+        None => INVALID_HANDLE,
+    }
+}
+
+/// # Safety
+///
+/// ctx must be valid
+#[no_mangle]
+pub unsafe extern "C" fn trussfs_path_join(
+    ctx: *mut Context,
+    base: *const c_char,
+    part: *const c_char,
+) -> u64 {
+    let ctx = &mut *ctx;
+    let base = c_str_to_string(base);
+    let part = c_str_to_string(part);
+    match ctx.string_handle(Ok(pathutil::join(&base, &part))) {
+        Some(handle) => handle.into(),
+        None => INVALID_HANDLE,
+    }
+}
+
+/// # Safety
+///
+/// ctx must be valid
+#[no_mangle]
+pub unsafe extern "C" fn trussfs_path_normalize(ctx: *mut Context, path: *const c_char) -> u64 {
+    let ctx = &mut *ctx;
+    let path = c_str_to_string(path);
+    match ctx.string_handle(Ok(pathutil::normalize(&path))) {
+        Some(handle) => handle.into(),
+        None => INVALID_HANDLE,
+    }
+}
+
+/// # Safety
+///
+/// ctx must be valid
+#[no_mangle]
+pub unsafe extern "C" fn trussfs_path_canonicalize(ctx: *mut Context, path: *const c_char) -> u64 {
+    let ctx = &mut *ctx;
+    let path = c_str_to_string(path);
+    match ctx.string_handle(pathutil::canonicalize(&path)) {
+        Some(handle) => handle.into(),
+        None => INVALID_HANDLE,
+    }
+}
+
+/// # Safety
+///
+/// ctx must be valid
+#[no_mangle]
+pub unsafe extern "C" fn trussfs_path_relative(
+    ctx: *mut Context,
+    base: *const c_char,
+    target: *const c_char,
+) -> u64 {
+    let ctx = &mut *ctx;
+    let base = c_str_to_string(base);
+    let target = c_str_to_string(target);
+    match ctx.string_handle(pathutil::relative(&base, &target)) {
+        Some(handle) => handle.into(),
+        None => INVALID_HANDLE,
+    }
+}
+
+/// # Safety
+///
+/// ctx must be valid
+#[no_mangle]
+pub unsafe extern "C" fn trussfs_path_parent(ctx: *mut Context, path: *const c_char) -> u64 {
+    let ctx = &mut *ctx;
+    let path = c_str_to_string(path);
+    match ctx.string_handle(pathutil::parent(&path)) {
+        Some(handle) => handle.into(),
+        None => INVALID_HANDLE,
+    }
+}
+
+/// # Safety
+///
+/// ctx must be valid
+#[no_mangle]
+pub unsafe extern "C" fn trussfs_path_extension(ctx: *mut Context, path: *const c_char) -> u64 {
+    let ctx = &mut *ctx;
+    let path = c_str_to_string(path);
+    match ctx.string_handle(Ok(pathutil::extension(&path))) {
+        Some(handle) => handle.into(),
+        None => INVALID_HANDLE,
+    }
+}
+
+/// # Safety
+///
+/// ctx must be valid
+#[no_mangle]
+pub unsafe extern "C" fn trussfs_path_stem(ctx: *mut Context, path: *const c_char) -> u64 {
+    let ctx = &mut *ctx;
+    let path = c_str_to_string(path);
+    match ctx.string_handle(Ok(pathutil::stem(&path))) {
+        Some(handle) => handle.into(),
+        None => INVALID_HANDLE,
+    }
+}
+
+/// # Safety
+///
+/// ctx must be valid
+#[no_mangle]
+pub unsafe extern "C" fn trussfs_string_get(
+    ctx: *mut Context,
+    string_handle: u64,
+) -> *const c_char {
+    let ctx = &mut *ctx;
+    match ctx.strings.get(string_handle.into()) {
+        Some(s) => s.as_ptr(),
+        None => {
+            warn!("String {} does not exist.", string_handle);
+            ptr::null()
+        }
+    }
+}
+
+/// # Safety
+///
+/// ctx must be valid
+#[no_mangle]
+pub unsafe extern "C" fn trussfs_string_free(ctx: *mut Context, string_handle: u64) {
+    let ctx = &mut *ctx;
+    ctx.strings.remove(string_handle.into());
+}
+
 /// # Safety
 ///
 /// ctx must be valid
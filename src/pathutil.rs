@@ -0,0 +1,121 @@
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+fn path_string(path: &Path) -> String {
+    path.to_string_lossy().into_owned()
+}
+
+pub fn join(base: &str, part: &str) -> String {
+    path_string(&Path::new(base).join(part))
+}
+
+fn normalize_path(path: &Path) -> PathBuf {
+    let mut result = PathBuf::new();
+    // number of normal components in `result` that a ".." can still pop
+    let mut depth = 0;
+    for component in path.components() {
+        match component {
+            Component::Prefix(_) | Component::RootDir => result.push(component),
+            Component::CurDir => (),
+            Component::ParentDir => {
+                if depth > 0 {
+                    result.pop();
+                    depth -= 1;
+                } else if !result.has_root() {
+                    // a relative path may still climb above its start
+                    result.push("..");
+                }
+            }
+            Component::Normal(part) => {
+                result.push(part);
+                depth += 1;
+            }
+        }
+    }
+    if result.as_os_str().is_empty() {
+        result.push(".");
+    }
+    result
+}
+
+/// Lexically resolve "." and ".." without touching the filesystem; ".."
+/// above the root of an absolute path is dropped.
+pub fn normalize(path: &str) -> String {
+    path_string(&normalize_path(Path::new(path)))
+}
+
+pub fn canonicalize(path: &str) -> Result<String, String> {
+    let path = fs::canonicalize(path).map_err(|e| format!("{}: {}", path, e))?;
+    Ok(path_string(&path))
+}
+
+/// Lexical path from `base` to `target`; both must be absolute or both
+/// relative (and on the same drive).
+pub fn relative(base: &str, target: &str) -> Result<String, String> {
+    let base = normalize_path(Path::new(base));
+    let target = normalize_path(Path::new(target));
+    if base.has_root() != target.has_root() {
+        return Err(format!(
+            "Cannot relate {} and {}: one is absolute and one is relative",
+            path_string(&base),
+            path_string(&target)
+        ));
+    }
+    let base_parts: Vec<Component> = base
+        .components()
+        .filter(|c| *c != Component::CurDir)
+        .collect();
+    let target_parts: Vec<Component> = target
+        .components()
+        .filter(|c| *c != Component::CurDir)
+        .collect();
+    let common = base_parts
+        .iter()
+        .zip(target_parts.iter())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let mut result = PathBuf::new();
+    for part in &base_parts[common..] {
+        match part {
+            Component::Normal(_) => result.push(".."),
+            _ => {
+                return Err(format!(
+                    "Cannot relate {} and {}",
+                    path_string(&base),
+                    path_string(&target)
+                ))
+            }
+        }
+    }
+    for part in &target_parts[common..] {
+        result.push(part);
+    }
+    if result.as_os_str().is_empty() {
+        result.push(".");
+    }
+    Ok(path_string(&result))
+}
+
+/// The path without its last component; a bare relative name like "a" has
+/// the parent ".". Roots and empty paths have no parent.
+pub fn parent(path: &str) -> Result<String, String> {
+    match Path::new(path).parent() {
+        Some(parent) if parent.as_os_str().is_empty() => Ok(String::from(".")),
+        Some(parent) => Ok(path_string(parent)),
+        None => Err(format!("Path has no parent: {}", path)),
+    }
+}
+
+pub fn extension(path: &str) -> String {
+    match Path::new(path).extension() {
+        Some(ext) => ext.to_string_lossy().into_owned(),
+        None => String::new(),
+    }
+}
+
+pub fn stem(path: &str) -> String {
+    match Path::new(path).file_stem() {
+        Some(stem) => stem.to_string_lossy().into_owned(),
+        None => String::new(),
+    }
+}
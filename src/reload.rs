@@ -0,0 +1,172 @@
+use crate::watcher::{FileWatcher, WakeSignal, WatcherOptions, EVENT_ACCESS};
+use std::collections::{BTreeSet, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// Maps the files things were loaded from back to the logical names they
+/// were loaded as, and collects the names whose files have changed.
+///
+/// Parent directories are watched rather than the files themselves, since
+/// editors commonly save by replacing the file, which would end a watch on
+/// the file. Several names may share one source file (e.g. every entry
+/// loaded out of the same archive).
+pub struct ReloadTracker {
+    watcher: FileWatcher,
+    // source file -> names loaded from it
+    sources: HashMap<PathBuf, BTreeSet<String>>,
+    // name -> source file
+    names: HashMap<String, PathBuf>,
+    // watched directory -> number of sources in it
+    dirs: HashMap<PathBuf, usize>,
+    // tracked directories whose watch ended (the directory was deleted),
+    // watched again once they reappear
+    lost: BTreeSet<PathBuf>,
+    stale: BTreeSet<String>,
+    errors: Vec<String>,
+}
+
+// Absolute form of `path` as the watcher will report it: the parent is
+// canonicalized, the file itself needn't exist
+fn source_path(path: &Path) -> Result<(PathBuf, PathBuf), String> {
+    let name = match path.file_name() {
+        Some(name) => name,
+        None => return Err(format!("Not a file path: {}", path.to_string_lossy())),
+    };
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let dir =
+        fs::canonicalize(parent).map_err(|e| format!("{}: {}", parent.to_string_lossy(), e))?;
+    Ok((dir.join(name), dir))
+}
+
+impl ReloadTracker {
+    pub fn new(debounce_ms: u32, signal: Arc<WakeSignal>) -> Result<Self, String> {
+        let options = WatcherOptions {
+            debounce_ms,
+            ..Default::default()
+        };
+        Ok(ReloadTracker {
+            watcher: FileWatcher::new(&options, signal)?,
+            sources: HashMap::new(),
+            names: HashMap::new(),
+            dirs: HashMap::new(),
+            lost: BTreeSet::new(),
+            stale: BTreeSet::new(),
+            errors: Vec::new(),
+        })
+    }
+
+    /// Track `name` as loaded from `path`; tracking a name again moves it
+    /// to the new source.
+    pub fn track(&mut self, name: String, path: &Path) -> Result<(), String> {
+        let (source, dir) = source_path(path)?;
+        self.check_dirs();
+        if self.names.get(&name) == Some(&source) {
+            return Ok(());
+        }
+        self.untrack(&name);
+        if !self.dirs.contains_key(&dir) {
+            self.watcher
+                .watch(dir.to_string_lossy().into_owned(), false)?;
+        }
+        let names = self.sources.entry(source.clone()).or_default();
+        if names.is_empty() {
+            *self.dirs.entry(dir).or_insert(0) += 1;
+        }
+        names.insert(name.clone());
+        self.names.insert(name, source);
+        Ok(())
+    }
+
+    pub fn untrack(&mut self, name: &str) -> bool {
+        self.stale.remove(name);
+        let source = match self.names.remove(name) {
+            Some(source) => source,
+            None => return false,
+        };
+        let names = self.sources.get_mut(&source).unwrap();
+        names.remove(name);
+        if !names.is_empty() {
+            return true;
+        }
+        self.sources.remove(&source);
+        let dir = source.parent().unwrap().to_path_buf();
+        let count = self.dirs.get_mut(&dir).unwrap();
+        *count -= 1;
+        if *count == 0 {
+            self.dirs.remove(&dir);
+            if !self.lost.remove(&dir) {
+                let _ = self.watcher.unwatch(dir.to_string_lossy().into_owned());
+            }
+        }
+        true
+    }
+
+    /// Names whose sources changed since the last poll, sorted.
+    pub fn poll(&mut self) -> Vec<String> {
+        for evt in self.watcher.poll_typed_events() {
+            if evt.kind == EVENT_ACCESS {
+                continue;
+            }
+            for path in std::iter::once(&evt.path).chain(evt.path2.as_ref()) {
+                if let Some(names) = self.sources.get(path) {
+                    self.stale.extend(names.iter().cloned());
+                }
+            }
+        }
+        self.check_dirs();
+        std::mem::take(&mut self.stale).into_iter().collect()
+    }
+
+    /// Errors since the last call (e.g. a watched directory was deleted),
+    /// joined into one message.
+    pub fn error(&mut self) -> Option<String> {
+        if self.errors.is_empty() {
+            None
+        } else {
+            Some(std::mem::take(&mut self.errors).join("; "))
+        }
+    }
+
+    // The watcher drops a directory once it's deleted; remember it as lost
+    // and watch it again when it reappears. Its sources may have changed
+    // while unwatched, so their names go stale then.
+    fn check_dirs(&mut self) {
+        for e in self.watcher.poll_errors() {
+            self.errors.push(e.to_string_lossy().into_owned());
+        }
+        let watched: BTreeSet<PathBuf> = self
+            .watcher
+            .watched_paths()
+            .into_iter()
+            .map(PathBuf::from)
+            .collect();
+        for dir in self.dirs.keys() {
+            if !watched.contains(dir) {
+                self.lost.insert(dir.clone());
+            }
+        }
+        let lost: Vec<PathBuf> = self.lost.iter().cloned().collect();
+        for dir in lost {
+            if !dir.is_dir() {
+                continue;
+            }
+            if let Err(e) = self
+                .watcher
+                .watch(dir.to_string_lossy().into_owned(), false)
+            {
+                self.errors.push(e);
+                continue;
+            }
+            self.lost.remove(&dir);
+            for (source, names) in &self.sources {
+                if source.parent() == Some(dir.as_path()) {
+                    self.stale.extend(names.iter().cloned());
+                }
+            }
+        }
+    }
+}
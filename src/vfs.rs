@@ -0,0 +1,291 @@
+use crate::archive::Archive;
+use crate::context::{ArchiveKey, HandleMap, MountKey};
+use std::cmp::Reverse;
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+pub enum MountSource {
+    Dir(PathBuf),
+    Archive(ArchiveKey),
+}
+
+pub struct Mount {
+    prefix: String,
+    priority: i64,
+    order: u64,
+    source: MountSource,
+}
+
+pub struct Vfs {
+    mounts: HandleMap<MountKey, Mount>,
+    next_order: u64,
+}
+
+/// Normalize a virtual path into "a/b/c" form: both separators are accepted,
+/// empty and "." segments are dropped, and ".." is rejected outright.
+pub fn normalize_vpath(path: &str) -> Result<String, String> {
+    let mut parts: Vec<&str> = Vec::new();
+    for part in path.split(['/', '\\']) {
+        match part {
+            "" | "." => continue,
+            ".." => return Err(format!("Virtual path may not contain '..': {}", path)),
+            _ => parts.push(part),
+        }
+    }
+    Ok(parts.join("/"))
+}
+
+// Path of `vpath` relative to `prefix`, if `vpath` is inside the prefix
+fn strip_mount_prefix<'a>(prefix: &str, vpath: &'a str) -> Option<&'a str> {
+    if prefix.is_empty() {
+        return Some(vpath);
+    }
+    let rest = vpath.strip_prefix(prefix)?;
+    if rest.is_empty() {
+        Some(rest)
+    } else {
+        rest.strip_prefix('/')
+    }
+}
+
+// `rel` under a directory mount. Normalized vpaths have no ".." segments,
+// but a segment can still parse as a drive or prefix (e.g. "C:" on Windows),
+// which `join` would let replace the mount directory.
+fn dir_path(dir: &Path, rel: &str) -> Option<PathBuf> {
+    if Path::new(rel)
+        .components()
+        .all(|c| matches!(c, Component::Normal(_)))
+    {
+        Some(dir.join(rel))
+    } else {
+        None
+    }
+}
+
+impl Mount {
+    fn is_file(&self, rel: &str, archives: &HandleMap<ArchiveKey, Archive>) -> bool {
+        match &self.source {
+            MountSource::Dir(dir) => dir_path(dir, rel).is_some_and(|path| path.is_file()),
+            MountSource::Archive(key) => match archives.get(*key) {
+                Some(archive) => archive.is_file(rel),
+                None => false,
+            },
+        }
+    }
+
+    fn is_dir(&self, rel: &str, archives: &HandleMap<ArchiveKey, Archive>) -> bool {
+        match &self.source {
+            MountSource::Dir(dir) => dir_path(dir, rel).is_some_and(|path| path.is_dir()),
+            MountSource::Archive(key) => match archives.get(*key) {
+                Some(archive) => archive.is_dir(rel),
+                None => false,
+            },
+        }
+    }
+
+    fn children(
+        &self,
+        rel: &str,
+        archives: &HandleMap<ArchiveKey, Archive>,
+    ) -> Vec<(String, bool)> {
+        match &self.source {
+            MountSource::Dir(dir) => {
+                let entries = match dir_path(dir, rel).map(fs::read_dir) {
+                    Some(Ok(entries)) => entries,
+                    _ => return Vec::new(),
+                };
+                entries
+                    .filter_map(|entry| {
+                        let entry = entry.ok()?;
+                        let is_dir = fs::metadata(entry.path()).ok()?.is_dir();
+                        Some((entry.file_name().to_string_lossy().into_owned(), is_dir))
+                    })
+                    .collect()
+            }
+            MountSource::Archive(key) => match archives.get(*key) {
+                Some(archive) => archive.dir_children(rel),
+                None => Vec::new(),
+            },
+        }
+    }
+}
+
+impl Vfs {
+    pub fn new() -> Self {
+        Vfs {
+            mounts: HandleMap::with_key(),
+            next_order: 0,
+        }
+    }
+
+    pub fn mount(
+        &mut self,
+        prefix: &str,
+        source: MountSource,
+        priority: i64,
+    ) -> Result<MountKey, String> {
+        let prefix = normalize_vpath(prefix)?;
+        let order = self.next_order;
+        self.next_order += 1;
+        Ok(self.mounts.insert(Mount {
+            prefix,
+            priority,
+            order,
+            source,
+        }))
+    }
+
+    pub fn unmount(&mut self, mount: MountKey) -> bool {
+        self.mounts.remove(mount).is_some()
+    }
+
+    // Mounts in resolution order: highest priority first, and among equal
+    // priorities the most recently mounted first.
+    fn ordered(&self) -> Vec<&Mount> {
+        let mut mounts: Vec<&Mount> = self.mounts.values().collect();
+        mounts.sort_by_key(|m| Reverse((m.priority, m.order)));
+        mounts
+    }
+
+    fn find_file(
+        &self,
+        vpath: &str,
+        archives: &HandleMap<ArchiveKey, Archive>,
+    ) -> Option<(&Mount, String)> {
+        self.ordered().into_iter().find_map(|mount| {
+            let rel = strip_mount_prefix(&mount.prefix, vpath)?;
+            if mount.is_file(rel, archives) {
+                Some((mount, rel.to_string()))
+            } else {
+                None
+            }
+        })
+    }
+
+    pub fn read(
+        &self,
+        vpath: &str,
+        archives: &mut HandleMap<ArchiveKey, Archive>,
+    ) -> Result<Vec<u8>, String> {
+        let vpath = normalize_vpath(vpath)?;
+        let (mount, rel) = match self.find_file(&vpath, archives) {
+            Some(found) => found,
+            None => return Err(format!("No such file: {}", vpath)),
+        };
+        match &mount.source {
+            MountSource::Dir(dir) => match dir_path(dir, &rel) {
+                Some(path) => fs::read(path).map_err(|e| e.to_string()),
+                None => Err(format!("No such file: {}", vpath)),
+            },
+            MountSource::Archive(key) => match archives.get_mut(*key) {
+                Some(archive) => archive.read_file_by_path(&rel),
+                None => Err(String::from("No such archive")),
+            },
+        }
+    }
+
+    pub fn filesize(
+        &self,
+        vpath: &str,
+        archives: &mut HandleMap<ArchiveKey, Archive>,
+    ) -> Result<u64, String> {
+        let vpath = normalize_vpath(vpath)?;
+        let (mount, rel) = match self.find_file(&vpath, archives) {
+            Some(found) => found,
+            None => return Err(format!("No such file: {}", vpath)),
+        };
+        match &mount.source {
+            MountSource::Dir(dir) => match dir_path(dir, &rel) {
+                Some(path) => fs::metadata(path)
+                    .map(|m| m.len())
+                    .map_err(|e| e.to_string()),
+                None => Err(format!("No such file: {}", vpath)),
+            },
+            MountSource::Archive(key) => match archives.get_mut(*key) {
+                Some(archive) => archive.filesize_by_path(&rel),
+                None => Err(String::from("No such archive")),
+            },
+        }
+    }
+
+    /// The file on disk that `vpath` is currently served from: the file
+    /// itself for directory mounts, or the zip file for archive mounts.
+    pub fn source_path(
+        &self,
+        vpath: &str,
+        archives: &HandleMap<ArchiveKey, Archive>,
+    ) -> Result<PathBuf, String> {
+        let vpath = normalize_vpath(vpath)?;
+        let (mount, rel) = match self.find_file(&vpath, archives) {
+            Some(found) => found,
+            None => return Err(format!("No such file: {}", vpath)),
+        };
+        match &mount.source {
+            MountSource::Dir(dir) => match dir_path(dir, &rel) {
+                Some(path) => Ok(path),
+                None => Err(format!("No such file: {}", vpath)),
+            },
+            MountSource::Archive(key) => match archives.get(*key) {
+                Some(archive) => archive.source_file(),
+                None => Err(String::from("No such archive")),
+            },
+        }
+    }
+
+    pub fn exists(&self, vpath: &str, archives: &HandleMap<ArchiveKey, Archive>) -> bool {
+        let vpath = match normalize_vpath(vpath) {
+            Ok(vpath) => vpath,
+            Err(_) => return false,
+        };
+        self.mounts.values().any(|mount| {
+            match strip_mount_prefix(&mount.prefix, &vpath) {
+                Some(rel) => mount.is_file(rel, archives) || mount.is_dir(rel, archives),
+                // a parent of a mount point exists as a virtual directory
+                None => strip_mount_prefix(&vpath, &mount.prefix).is_some(),
+            }
+        })
+    }
+
+    /// Names of the immediate children of a virtual directory, merged across
+    /// every mount that contributes to it, in sorted order.
+    pub fn list(
+        &self,
+        vpath: &str,
+        files_only: bool,
+        archives: &HandleMap<ArchiveKey, Archive>,
+    ) -> Result<Vec<String>, String> {
+        let vpath = normalize_vpath(vpath)?;
+        let mut found = false;
+        let mut names: BTreeSet<String> = BTreeSet::new();
+        for mount in self.ordered() {
+            if let Some(rel) = strip_mount_prefix(&mount.prefix, &vpath) {
+                if !mount.is_dir(rel, archives) {
+                    continue;
+                }
+                found = true;
+                for (name, is_dir) in mount.children(rel, archives) {
+                    if !(files_only && is_dir) {
+                        names.insert(name);
+                    }
+                }
+            } else if let Some(rest) = strip_mount_prefix(&vpath, &mount.prefix) {
+                found = true;
+                if !files_only {
+                    let child = rest.split('/').next().unwrap_or_default();
+                    names.insert(child.to_string());
+                }
+            }
+        }
+        if !found {
+            return Err(format!("No such directory: {}", vpath));
+        }
+        Ok(names.into_iter().collect())
+    }
+}
+
+impl Default for Vfs {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,126 @@
+use globset::{Glob, GlobMatcher, GlobSet, GlobSetBuilder};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub struct WalkOptions {
+    pub include: Option<GlobMatcher>,
+    pub exclude: Option<GlobMatcher>,
+    pub max_depth: u32,
+    pub follow_symlinks: bool,
+}
+
+/// Compile a glob pattern; an empty pattern means "no filter".
+pub fn compile_glob(pattern: &str) -> Result<Option<GlobMatcher>, String> {
+    if pattern.is_empty() {
+        return Ok(None);
+    }
+    let glob = Glob::new(pattern).map_err(|e| e.to_string())?;
+    Ok(Some(glob.compile_matcher()))
+}
+
+/// Compile several glob patterns into one set; no patterns means "no filter".
+pub fn compile_globset(patterns: &[String]) -> Result<Option<GlobSet>, String> {
+    if patterns.is_empty() {
+        return Ok(None);
+    }
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        builder.add(Glob::new(pattern).map_err(|e| e.to_string())?);
+    }
+    Ok(Some(builder.build().map_err(|e| e.to_string())?))
+}
+
+struct Walker<'a> {
+    options: &'a WalkOptions,
+    // real paths of the directories currently being walked, outermost first
+    ancestors: Vec<PathBuf>,
+    files: Vec<String>,
+}
+
+impl<'a> Walker<'a> {
+    fn walk(&mut self, dir: &Path, rel: &str, depth: u32) {
+        if !self.options.follow_symlinks {
+            self.walk_entries(dir, rel, depth);
+            return;
+        }
+        // a symlink back to a directory that is already being walked is a
+        // cycle; other directories reached by more than one path are walked
+        // under each of them
+        let real = match fs::canonicalize(dir) {
+            Ok(real) => real,
+            Err(_) => return,
+        };
+        if self.ancestors.contains(&real) {
+            return;
+        }
+        self.ancestors.push(real);
+        self.walk_entries(dir, rel, depth);
+        self.ancestors.pop();
+    }
+
+    fn walk_entries(&mut self, dir: &Path, rel: &str, depth: u32) {
+        let mut entries: Vec<fs::DirEntry> = match fs::read_dir(dir) {
+            Ok(entries) => entries.flatten().collect(),
+            Err(_) => return,
+        };
+        // fixed order, so which path reaches what doesn't depend on read_dir
+        entries.sort_by_key(|entry| entry.file_name());
+        for entry in entries {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let child_rel = if rel.is_empty() {
+                name
+            } else {
+                format!("{}/{}", rel, name)
+            };
+            if let Some(exclude) = &self.options.exclude {
+                if exclude.is_match(&child_rel) {
+                    continue;
+                }
+            }
+            let path = entry.path();
+            let metadata = if self.options.follow_symlinks {
+                fs::metadata(&path)
+            } else {
+                fs::symlink_metadata(&path)
+            };
+            let metadata = match metadata {
+                Ok(metadata) => metadata,
+                Err(_) => continue,
+            };
+            if metadata.is_dir() {
+                if self.options.max_depth == 0 || depth < self.options.max_depth {
+                    self.walk(&path, &child_rel, depth + 1);
+                }
+            } else if metadata.is_file() || (metadata.is_symlink() && path.is_file()) {
+                let included = match &self.options.include {
+                    Some(include) => include.is_match(&child_rel),
+                    None => true,
+                };
+                if included {
+                    self.files.push(child_rel);
+                }
+            }
+        }
+    }
+}
+
+/// Recursively collect the files under `root` as sorted, '/'-separated paths
+/// relative to `root`. Excluded paths are pruned (an excluded directory is
+/// not descended into), and `max_depth` limits how many directory levels
+/// are entered, with 0 meaning unlimited. When following symlinks, a
+/// directory reachable by several paths is listed under each of them; only
+/// links back into a directory being walked (cycles) are skipped.
+pub fn walk_dir(root: &str, options: &WalkOptions) -> Result<Vec<String>, String> {
+    let root = Path::new(root);
+    if !root.is_dir() {
+        return Err(format!("Not a directory: {}", root.to_string_lossy()));
+    }
+    let mut walker = Walker {
+        options,
+        ancestors: Vec::new(),
+        files: Vec::new(),
+    };
+    walker.walk(root, "", 1);
+    walker.files.sort();
+    Ok(walker.files)
+}
@@ -1,11 +1,223 @@
 use crate::context::StringList;
+use crate::debounce::{is_debounceable, Debouncer};
+use crate::walk::compile_globset;
+use globset::GlobSet;
 use log::info;
-use notify::{Config, Error, Event, RecommendedWatcher, RecursiveMode, Watcher};
+use notify::event::{AccessKind, CreateKind, ModifyKind, RemoveKind, RenameMode};
+use notify::{
+    Config, Error, Event, EventKind, PollWatcher, RecommendedWatcher, RecursiveMode, Watcher,
+    WatcherKind,
+};
+use std::collections::{BTreeMap, HashMap};
 use std::ffi::CString;
+use std::os::raw::{c_char, c_void};
+use std::path::{Path, PathBuf};
+use std::ptr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{Receiver, Sender};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, OnceLock, RwLock};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+pub const BACKEND_NATIVE: u32 = 0;
+pub const BACKEND_POLL: u32 = 1;
+pub const BACKEND_AUTO: u32 = 2;
+
+pub const WATCHER_KIND_OTHER: u32 = 0;
+pub const WATCHER_KIND_INOTIFY: u32 = 1;
+pub const WATCHER_KIND_FSEVENT: u32 = 2;
+pub const WATCHER_KIND_KQUEUE: u32 = 3;
+pub const WATCHER_KIND_POLL: u32 = 4;
+pub const WATCHER_KIND_WINDOWS: u32 = 5;
+
+pub const WAIT_TIMEOUT: i64 = -1;
+pub const WAIT_ERROR: i64 = -2;
+
+const DEFAULT_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+pub const EVENT_OTHER: u32 = 0;
+pub const EVENT_CREATE: u32 = 1;
+pub const EVENT_REMOVE: u32 = 2;
+pub const EVENT_MODIFY: u32 = 3;
+pub const EVENT_RENAME: u32 = 4;
+pub const EVENT_ACCESS: u32 = 5;
+
+pub const SUBKIND_ANY: u32 = 0;
+pub const SUBKIND_FILE: u32 = 1;
+pub const SUBKIND_FOLDER: u32 = 2;
+pub const SUBKIND_DATA: u32 = 3;
+pub const SUBKIND_METADATA: u32 = 4;
+pub const SUBKIND_OPEN: u32 = 5;
+pub const SUBKIND_CLOSE: u32 = 6;
+pub const SUBKIND_READ: u32 = 7;
+pub const SUBKIND_RENAME_FROM: u32 = 8;
+pub const SUBKIND_RENAME_TO: u32 = 9;
+
+// How long the first half of a rename is held back waiting for its partner
+const RENAME_PAIR_WAIT: Duration = Duration::from_millis(50);
+
+type Message = (Instant, Event);
+
+/// `backend` is one of the BACKEND_* values: BACKEND_AUTO uses the native
+/// backend but falls back to polling if that can't be set up. A zero
+/// `poll_interval_ms` uses the default interval, and a nonzero
+/// `debounce_ms` coalesces each path's events into a single event once the
+/// path has been quiet for that long.
+#[derive(Clone, Copy, Default)]
+pub struct WatcherOptions {
+    pub backend: u32,
+    pub poll_interval_ms: u32,
+    pub debounce_ms: u32,
+}
+
+/// Shared by a context's watchers so that one thread can block until any of
+/// them receives something.
+#[derive(Default)]
+pub struct WakeSignal {
+    generation: Mutex<u64>,
+    cond: Condvar,
+}
+
+impl WakeSignal {
+    fn notify(&self) {
+        *self.generation.lock().unwrap() += 1;
+        self.cond.notify_all();
+    }
+
+    pub fn generation(&self) -> u64 {
+        *self.generation.lock().unwrap()
+    }
+
+    /// Block until the generation moves past `since` or `until` is reached.
+    pub fn wait(&self, since: u64, until: Option<Instant>) {
+        let mut generation = self.generation.lock().unwrap();
+        while *generation == since {
+            generation = match until {
+                None => self.cond.wait(generation).unwrap(),
+                Some(until) => {
+                    let now = Instant::now();
+                    if now >= until {
+                        return;
+                    }
+                    self.cond.wait_timeout(generation, until - now).unwrap().0
+                }
+            };
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct PathFilter {
+    include: Option<GlobSet>,
+    exclude: Option<GlobSet>,
+}
+
+/// Signature of `trussfs_watch_callback` in trussfs.h.
+pub type WatchCallback = unsafe extern "C" fn(userdata: *mut c_void, event: *const CWatchEvent);
+
+// everything between the backend's channels and a poll, shared with the
+// dispatch thread when a callback is set
+struct EventQueue {
+    recv: Receiver<Message>,
+    error_recv: Receiver<String>,
+    errors: Vec<String>,
+    // cleared by any backend error or a watched root going away
+    healthy: bool,
+    debouncer: Option<Debouncer>,
+    pending_renames: Vec<WatchEvent>,
+    // messages taken off the channel that haven't been polled yet
+    ready: Vec<Message>,
+    // watched path -> recursive
+    watched: BTreeMap<String, bool>,
+    // removed roots that still need unwatching on the backend
+    lost: Vec<String>,
+    // woken when errors are found here rather than arriving on a channel
+    signal: Arc<WakeSignal>,
+}
+
+struct CallbackTarget {
+    callback: WatchCallback,
+    userdata: *mut c_void,
+}
+
+// the host promises the callback and userdata are usable from the dispatch
+// thread; see trussfs_watcher_set_callback
+unsafe impl Send for CallbackTarget {}
+
+struct Dispatcher {
+    stop: Arc<AtomicBool>,
+    signal: Arc<WakeSignal>,
+    thread: Option<JoinHandle<()>>,
+}
 
 pub struct FileWatcher {
     inner: Box<dyn notify::Watcher>,
-    recv: std::sync::mpsc::Receiver<String>,
+    kind: WatcherKind,
+    filter: Arc<RwLock<PathFilter>>,
+    signal: Arc<WakeSignal>,
+    queue: Arc<Mutex<EventQueue>>,
+    dispatcher: Option<Dispatcher>,
+}
+
+#[derive(Clone)]
+pub struct WatchEvent {
+    pub kind: u32,
+    pub subkind: u32,
+    pub path: PathBuf,
+    pub path2: Option<PathBuf>,
+    pub time: Instant,
+    tracker: Option<usize>,
+}
+
+/// Mirrors `trussfs_watch_event` in trussfs.h; the paths are owned by the
+/// event list and `path2` is null unless this is a paired rename.
+#[repr(C)]
+pub struct CWatchEvent {
+    pub kind: u32,
+    pub subkind: u32,
+    pub path: *const c_char,
+    pub path2: *const c_char,
+    pub timestamp: f64,
+}
+
+pub struct EventList {
+    // backing storage for the path pointers in `entries`
+    _paths: Vec<CString>,
+    entries: Vec<CWatchEvent>,
+}
+
+/// Seconds on a monotonic clock (since the first time this is called).
+pub fn monotonic_secs(time: Instant) -> f64 {
+    static EPOCH: OnceLock<Instant> = OnceLock::new();
+    let epoch = *EPOCH.get_or_init(Instant::now);
+    time.saturating_duration_since(epoch).as_secs_f64()
+}
+
+impl PathFilter {
+    pub fn new(include: &[String], exclude: &[String]) -> Result<Self, String> {
+        Ok(PathFilter {
+            include: compile_globset(include)?,
+            exclude: compile_globset(exclude)?,
+        })
+    }
+
+    fn allows_path(&self, path: &Path) -> bool {
+        let included = match &self.include {
+            Some(include) => include.is_match(path),
+            None => true,
+        };
+        let excluded = match &self.exclude {
+            Some(exclude) => exclude.is_match(path),
+            None => false,
+        };
+        included && !excluded
+    }
+
+    /// Events with no paths always pass; otherwise any allowed path (e.g.
+    /// either end of a rename) lets the event through.
+    fn allows(&self, evt: &Event) -> bool {
+        evt.paths.is_empty() || evt.paths.iter().any(|path| self.allows_path(path))
+    }
 }
 
 fn event_kind_to_string(kind: notify::EventKind) -> &'static str {
@@ -29,34 +241,407 @@ fn event_to_string(evt: notify::Event) -> String {
     format!("{}:{}", event_kind_to_string(evt.kind), pathstr)
 }
 
-impl FileWatcher {
-    pub fn new() -> Result<Self, String> {
-        let (tx, rx) = std::sync::mpsc::channel();
+fn classify(kind: &EventKind) -> (u32, u32) {
+    match kind {
+        EventKind::Create(CreateKind::File) => (EVENT_CREATE, SUBKIND_FILE),
+        EventKind::Create(CreateKind::Folder) => (EVENT_CREATE, SUBKIND_FOLDER),
+        EventKind::Create(_) => (EVENT_CREATE, SUBKIND_ANY),
+        EventKind::Remove(RemoveKind::File) => (EVENT_REMOVE, SUBKIND_FILE),
+        EventKind::Remove(RemoveKind::Folder) => (EVENT_REMOVE, SUBKIND_FOLDER),
+        EventKind::Remove(_) => (EVENT_REMOVE, SUBKIND_ANY),
+        EventKind::Modify(ModifyKind::Data(_)) => (EVENT_MODIFY, SUBKIND_DATA),
+        EventKind::Modify(ModifyKind::Metadata(_)) => (EVENT_MODIFY, SUBKIND_METADATA),
+        EventKind::Modify(ModifyKind::Name(RenameMode::From)) => {
+            (EVENT_RENAME, SUBKIND_RENAME_FROM)
+        }
+        EventKind::Modify(ModifyKind::Name(RenameMode::To)) => (EVENT_RENAME, SUBKIND_RENAME_TO),
+        EventKind::Modify(ModifyKind::Name(_)) => (EVENT_RENAME, SUBKIND_ANY),
+        EventKind::Modify(_) => (EVENT_MODIFY, SUBKIND_ANY),
+        EventKind::Access(AccessKind::Open(_)) => (EVENT_ACCESS, SUBKIND_OPEN),
+        EventKind::Access(AccessKind::Close(_)) => (EVENT_ACCESS, SUBKIND_CLOSE),
+        EventKind::Access(AccessKind::Read) => (EVENT_ACCESS, SUBKIND_READ),
+        EventKind::Access(_) => (EVENT_ACCESS, SUBKIND_ANY),
+        _ => (EVENT_OTHER, SUBKIND_ANY),
+    }
+}
+
+fn to_watch_events(time: Instant, evt: Event) -> Vec<WatchEvent> {
+    let (kind, subkind) = classify(&evt.kind);
+    let tracker = evt.tracker();
+    let mut paths = evt.paths.into_iter();
+    if kind == EVENT_RENAME && subkind == SUBKIND_ANY && paths.len() >= 2 {
+        return vec![WatchEvent {
+            kind,
+            subkind,
+            path: paths.next().unwrap_or_default(),
+            path2: paths.next(),
+            time,
+            tracker,
+        }];
+    }
+    paths
+        .map(|path| WatchEvent {
+            kind,
+            subkind,
+            path,
+            path2: None,
+            time,
+            tracker,
+        })
+        .collect()
+}
+
+/// Merge the two halves of renames (From/To events sharing a tracker, or
+/// a combined Both event) into single events carrying both paths.
+fn pair_renames(events: Vec<WatchEvent>) -> Vec<WatchEvent> {
+    let mut paired: Vec<WatchEvent> = Vec::new();
+    let mut by_tracker: HashMap<usize, usize> = HashMap::new();
+    for evt in events {
+        let tracker = match (evt.kind, evt.tracker) {
+            (EVENT_RENAME, Some(tracker)) => tracker,
+            _ => {
+                paired.push(evt);
+                continue;
+            }
+        };
+        let existing = match by_tracker.get(&tracker) {
+            Some(idx) => &mut paired[*idx],
+            None => {
+                by_tracker.insert(tracker, paired.len());
+                paired.push(evt);
+                continue;
+            }
+        };
+        match evt.subkind {
+            SUBKIND_RENAME_TO if existing.subkind == SUBKIND_RENAME_FROM => {
+                existing.subkind = SUBKIND_ANY;
+                existing.path2 = Some(evt.path);
+            }
+            SUBKIND_ANY if evt.path2.is_some() => {
+                existing.subkind = SUBKIND_ANY;
+                existing.path = evt.path;
+                existing.path2 = evt.path2;
+            }
+            // duplicate halves of a rename we've already paired
+            _ => (),
+        }
+    }
+    paired
+}
+
+impl EventList {
+    pub fn new(events: Vec<WatchEvent>) -> Self {
+        let mut paths: Vec<CString> = Vec::new();
+        let mut entries: Vec<CWatchEvent> = Vec::new();
+        for evt in events {
+            let path = CString::new(evt.path.to_string_lossy().into_owned()).unwrap_or_default();
+            let path2 = evt
+                .path2
+                .map(|p| CString::new(p.to_string_lossy().into_owned()).unwrap_or_default());
+            // the CString buffers are on the heap, so pointers taken now
+            // stay valid as `paths` grows
+            entries.push(CWatchEvent {
+                kind: evt.kind,
+                subkind: evt.subkind,
+                path: path.as_ptr(),
+                path2: path2.as_ref().map_or(ptr::null(), |p| p.as_ptr()),
+                timestamp: monotonic_secs(evt.time),
+            });
+            paths.push(path);
+            paths.extend(path2);
+        }
+        EventList {
+            _paths: paths,
+            entries,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn get(&self, index: usize) -> Option<&CWatchEvent> {
+        self.entries.get(index)
+    }
+}
 
-        let watcher = RecommendedWatcher::new(
-            move |res: Result<Event, Error>| match res {
-                Ok(evt) => {
-                    tx.send(event_to_string(evt)).expect("Send error.");
+fn make_handler(
+    tx: Sender<Message>,
+    error_tx: Sender<String>,
+    filter: Arc<RwLock<PathFilter>>,
+    signal: Arc<WakeSignal>,
+) -> impl FnMut(Result<Event, Error>) + Send + 'static {
+    // Runs on the backend's thread, which can still deliver an event after
+    // the watcher (and so the receivers) has been dropped; such late sends
+    // fail and are ignored rather than panicking that thread.
+    move |res: Result<Event, Error>| {
+        match res {
+            Ok(evt) => {
+                if !filter.read().unwrap().allows(&evt) {
+                    return;
                 }
-                Err(e) => {
-                    tx.send(e.to_string()).expect("Send error");
+                let _ = tx.send((Instant::now(), evt));
+            }
+            Err(e) => {
+                let _ = error_tx.send(e.to_string());
+            }
+        }
+        signal.notify();
+    }
+}
+
+fn watcher_kind_to_u32(kind: WatcherKind) -> u32 {
+    match kind {
+        WatcherKind::Inotify => WATCHER_KIND_INOTIFY,
+        WatcherKind::Fsevent => WATCHER_KIND_FSEVENT,
+        WatcherKind::Kqueue => WATCHER_KIND_KQUEUE,
+        WatcherKind::PollWatcher => WATCHER_KIND_POLL,
+        WatcherKind::ReadDirectoryChangesWatcher => WATCHER_KIND_WINDOWS,
+        _ => WATCHER_KIND_OTHER,
+    }
+}
+
+impl EventQueue {
+    fn accept(&mut self, time: Instant, evt: Event) {
+        if evt.kind.is_remove() {
+            self.check_roots();
+        }
+        match &mut self.debouncer {
+            Some(debouncer) if is_debounceable(&evt) => debouncer.push(time, evt),
+            _ => self.ready.push((time, evt)),
+        }
+    }
+
+    // the native backends silently stop watching a deleted root, so drop
+    // it from the watched set and report it
+    fn check_roots(&mut self) {
+        let removed: Vec<String> = self
+            .watched
+            .keys()
+            .filter(|path| Path::new(path).symlink_metadata().is_err())
+            .cloned()
+            .collect();
+        if removed.is_empty() {
+            return;
+        }
+        for path in removed {
+            self.watched.remove(&path);
+            self.errors.push(format!("Watched path removed: {}", path));
+            self.healthy = false;
+            self.lost.push(path);
+        }
+        // the check may have run on the dispatch thread; wake any waiter
+        self.signal.notify();
+    }
+
+    fn collect_errors(&mut self) {
+        for e in self.error_recv.try_iter() {
+            self.errors.push(e);
+            self.healthy = false;
+        }
+    }
+
+    fn collect(&mut self, now: Instant) -> bool {
+        while let Ok((time, evt)) = self.recv.try_recv() {
+            self.accept(time, evt);
+        }
+        if let Some(debouncer) = &mut self.debouncer {
+            let settled = debouncer.flush(now);
+            self.ready.extend(settled);
+        }
+        !self.ready.is_empty()
+            || self
+                .pending_renames
+                .iter()
+                .any(|evt| now.duration_since(evt.time) >= RENAME_PAIR_WAIT)
+    }
+
+    fn next_wake(&self) -> Option<Instant> {
+        let debounced = self.debouncer.as_ref().and_then(|d| d.next_deadline());
+        let renames = self
+            .pending_renames
+            .iter()
+            .map(|evt| evt.time + RENAME_PAIR_WAIT)
+            .min();
+        debounced.into_iter().chain(renames).min()
+    }
+
+    fn drain_messages(&mut self) -> Vec<Message> {
+        self.collect(Instant::now());
+        std::mem::take(&mut self.ready)
+    }
+
+    fn poll_typed_events(&mut self) -> Vec<WatchEvent> {
+        let mut events: Vec<WatchEvent> = self.pending_renames.drain(..).collect();
+        for (time, evt) in self.drain_messages() {
+            events.extend(to_watch_events(time, evt));
+        }
+        let now = Instant::now();
+        let mut ready: Vec<WatchEvent> = Vec::new();
+        for evt in pair_renames(events) {
+            let unpaired = evt.kind == EVENT_RENAME && evt.subkind == SUBKIND_RENAME_FROM;
+            if unpaired && evt.tracker.is_some() && now.duration_since(evt.time) < RENAME_PAIR_WAIT
+            {
+                self.pending_renames.push(evt);
+            } else {
+                ready.push(evt);
+            }
+        }
+        ready
+    }
+}
+
+impl Dispatcher {
+    fn start(
+        target: CallbackTarget,
+        queue: Arc<Mutex<EventQueue>>,
+        signal: Arc<WakeSignal>,
+    ) -> Result<Self, String> {
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread_stop = stop.clone();
+        let thread_signal = signal.clone();
+        let thread = std::thread::Builder::new()
+            .name(String::from("trussfs-watch"))
+            .spawn(move || {
+                // capture the whole target rather than its (non-Send) fields
+                let target = target;
+                loop {
+                    let generation = thread_signal.generation();
+                    if thread_stop.load(Ordering::Acquire) {
+                        return;
+                    }
+                    let (events, wake) = {
+                        let mut queue = queue.lock().unwrap();
+                        (queue.poll_typed_events(), queue.next_wake())
+                    };
+                    // the batch is off the queue, so it is delivered in full
+                    // even if a stop comes in meanwhile; otherwise it would
+                    // be lost to both the callback and polling
+                    let events = EventList::new(events);
+                    for evt in &events.entries {
+                        unsafe { (target.callback)(target.userdata, evt) };
+                    }
+                    thread_signal.wait(generation, wake);
                 }
-            },
-            Config::default(),
-        )
-        .map_err(|e| e.to_string())?;
-        info!("Created watcher (kind: {:?})", RecommendedWatcher::kind());
+            })
+            .map_err(|e| e.to_string())?;
+        Ok(Dispatcher {
+            stop,
+            signal,
+            thread: Some(thread),
+        })
+    }
+}
 
-        Ok(Self {
-            inner: Box::new(watcher),
+impl Drop for Dispatcher {
+    fn drop(&mut self) {
+        // once this returns the callback is never invoked again; events
+        // still queued are left for polling
+        self.stop.store(true, Ordering::Release);
+        self.signal.notify();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl FileWatcher {
+    pub fn new(options: &WatcherOptions, signal: Arc<WakeSignal>) -> Result<Self, String> {
+        if options.backend > BACKEND_AUTO {
+            return Err(format!("Invalid watcher backend: {}", options.backend));
+        }
+        let (tx, rx) = std::sync::mpsc::channel();
+        let (error_tx, error_rx) = std::sync::mpsc::channel();
+        // pin the timestamp epoch before any events can arrive
+        monotonic_secs(Instant::now());
+
+        let filter = Arc::new(RwLock::new(PathFilter::default()));
+        let native = match options.backend {
+            BACKEND_NATIVE | BACKEND_AUTO => Some(RecommendedWatcher::new(
+                make_handler(tx.clone(), error_tx.clone(), filter.clone(), signal.clone()),
+                Config::default(),
+            )),
+            _ => None,
+        };
+        let (inner, kind): (Box<dyn notify::Watcher>, WatcherKind) = match native {
+            Some(Ok(watcher)) => (Box::new(watcher), RecommendedWatcher::kind()),
+            Some(Err(e)) if options.backend != BACKEND_AUTO => return Err(e.to_string()),
+            _ => {
+                let interval = match options.poll_interval_ms {
+                    0 => DEFAULT_POLL_INTERVAL,
+                    ms => Duration::from_millis(ms as u64),
+                };
+                let config = Config::default().with_poll_interval(interval);
+                let handler = make_handler(tx, error_tx, filter.clone(), signal.clone());
+                let watcher = PollWatcher::new(handler, config).map_err(|e| e.to_string())?;
+                (Box::new(watcher), PollWatcher::kind())
+            }
+        };
+        info!("Created watcher (kind: {:?})", kind);
+
+        let queue = EventQueue {
             recv: rx,
+            error_recv: error_rx,
+            errors: Vec::new(),
+            healthy: true,
+            debouncer: match options.debounce_ms {
+                0 => None,
+                ms => Some(Debouncer::new(Duration::from_millis(ms as u64))),
+            },
+            pending_renames: Vec::new(),
+            ready: Vec::new(),
+            watched: BTreeMap::new(),
+            lost: Vec::new(),
+            signal: signal.clone(),
+        };
+        Ok(Self {
+            inner,
+            kind,
+            filter,
+            signal,
+            queue: Arc::new(Mutex::new(queue)),
+            dispatcher: None,
         })
     }
 
+    // lock the queue, first unwatching any roots it found removed
+    fn queue(&mut self) -> MutexGuard<'_, EventQueue> {
+        let mut queue = self.queue.lock().unwrap();
+        for path in queue.lost.drain(..) {
+            let _ = self.inner.unwatch(path.as_ref());
+        }
+        queue
+    }
+
+    /// The active backend as one of the WATCHER_KIND_* values.
+    pub fn kind(&self) -> u32 {
+        watcher_kind_to_u32(self.kind)
+    }
+
+    /// Replace the filter applied to events before they are queued.
+    pub fn set_filter(&mut self, filter: PathFilter) {
+        *self.filter.write().unwrap() = filter;
+    }
+
     pub fn unwatch(&mut self, path: String) -> Result<(), String> {
+        // forget the path even if the backend complains (e.g. the directory
+        // was already deleted, which drops the native watch)
+        if self.queue().watched.remove(&path).is_none() {
+            return Err(format!("Not watching: {}", path));
+        }
+        info!("Unwatching: {}", path);
         self.inner.unwatch(path.as_ref()).map_err(|e| e.to_string())
     }
 
+    /// Watched paths (as they were passed to `watch`), sorted.
+    pub fn watched_paths(&mut self) -> Vec<String> {
+        self.queue().watched.keys().cloned().collect()
+    }
+
     pub fn watch(&mut self, path: String, recursive: bool) -> Result<(), String> {
         info!("Watching: {}", path);
         let mode = if recursive {
@@ -66,17 +651,82 @@ impl FileWatcher {
         };
         self.inner
             .watch(path.as_ref(), mode)
-            .map_err(|e| e.to_string())
+            .map_err(|e| e.to_string())?;
+        self.queue().watched.insert(path, recursive);
+        Ok(())
+    }
+
+    /// Deliver typed events to `callback` on a dispatch thread instead of
+    /// queueing them for polling; None stops the current dispatch thread,
+    /// waiting for any callback in progress to return.
+    pub fn set_callback(
+        &mut self,
+        callback: Option<WatchCallback>,
+        userdata: *mut c_void,
+    ) -> Result<(), String> {
+        self.dispatcher = None;
+        if let Some(callback) = callback {
+            let target = CallbackTarget { callback, userdata };
+            let dispatcher = Dispatcher::start(target, self.queue.clone(), self.signal.clone())?;
+            self.dispatcher = Some(dispatcher);
+        }
+        Ok(())
+    }
+
+    pub fn is_healthy(&mut self) -> bool {
+        let mut queue = self.queue();
+        queue.collect_errors();
+        queue.healthy
+    }
+
+    pub fn poll_errors(&mut self) -> StringList {
+        let mut queue = self.queue();
+        queue.collect_errors();
+        queue
+            .errors
+            .drain(..)
+            .map(|e| CString::new(e).expect("Errors shouldn't contain null bytes"))
+            .collect()
+    }
+
+    /// Take everything off the channels and flush settled debounced events,
+    /// returning whether a poll (of events or errors) would produce
+    /// anything. Events never count while a callback is set, since the
+    /// dispatch thread takes them, but unpolled errors always do.
+    pub fn collect(&mut self, now: Instant) -> bool {
+        let dispatching = self.dispatcher.is_some();
+        let mut queue = self.queue();
+        queue.collect_errors();
+        !queue.errors.is_empty() || (!dispatching && queue.collect(now))
+    }
+
+    /// The next time something held back (debounced paths, unpaired
+    /// renames) may become ready without any new events arriving.
+    pub fn next_wake(&mut self) -> Option<Instant> {
+        self.queue().next_wake()
     }
 
     pub fn poll_events(&mut self) -> StringList {
+        if self.dispatcher.is_some() {
+            return StringList::new();
+        }
         let mut events = StringList::new();
-        while let Ok(evt) = self.recv.try_recv() {
+        for (_, evt) in self.queue().drain_messages() {
             events.push(
-                CString::new(evt)
+                CString::new(event_to_string(evt))
                     .expect("It should be impossible for a std::String to contain null bytes!"),
             );
         }
         events
     }
+
+    /// Drain typed events, pairing up rename halves. A lone "rename from"
+    /// that is still fresh is held back until the next poll in case its
+    /// "rename to" half hasn't been delivered yet.
+    pub fn poll_typed_events(&mut self) -> Vec<WatchEvent> {
+        if self.dispatcher.is_some() {
+            return Vec::new();
+        }
+        self.queue().poll_typed_events()
+    }
 }